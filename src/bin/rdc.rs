@@ -1,9 +1,28 @@
 extern crate rdc;
 
+use std::io::{IsTerminal, Write};
+
 fn main() {
     // let us implement the real app to understand approaches to ownership
 
     let args = std::env::args().skip(1);
 
-    rdc::dc(args, std::io::stdout(), std::io::stderr());
+    let mut options = rdc::Options::default();
+    if let Ok(line_length) = std::env::var("DC_LINE_LENGTH") {
+        options.line_length = rdc::vm::parse_line_length(&line_length);
+    }
+
+    let stdin = std::io::stdin();
+    options.interactive = stdin.is_terminal();
+    let (mut stdout, _, status) = rdc::dc_with_options(
+        args,
+        stdin.lock(),
+        std::io::stdout(),
+        std::io::stderr(),
+        &options,
+    );
+    if stdout.flush().is_err() {
+        std::process::exit(rdc::EXIT_FAILURE);
+    }
+    std::process::exit(status);
 }
@@ -3,13 +3,15 @@ use std::error;
 use std::str;
 use std::str::FromStr;
 use std::io;
-use num::Num;
+use num::{Integer, Num, Signed, ToPrimitive, Zero};
 
 use bigdecimal;
 use bigdecimal::BigDecimal;
 use num::bigint;
 use std::ops::*;
 
+use instructions::{Register, Sign};
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum MemoryCell {
     Str(Vec<u8>),
@@ -61,10 +63,26 @@ impl MemoryCell {
     pub fn to_str_radix(&self, radix: u32) -> String {
         match self {
             &MemoryCell::Num(ref n) => to_string_radix(n, radix),
-            &MemoryCell::Str(ref v) => String::from_utf8(v.to_vec()).expect("internal utf8 error"),
+            &MemoryCell::Str(ref v) => String::from_utf8_lossy(v).into_owned(),
         }
     }
 
+    /// Writes the line `p` prints: numbers are wrapped at `line_length`
+    /// characters (0 disables wrapping), strings are written byte for byte.
+    pub fn write_to<W>(&self, w: &mut W, radix: u32, line_length: usize) -> Result<(), io::Error>
+    where
+        W: io::Write,
+    {
+        match *self {
+            MemoryCell::Num(ref n) => {
+                let text = wrap_line(&to_string_radix(n, radix), line_length);
+                w.write_all(text.as_bytes())?
+            }
+            MemoryCell::Str(ref bytes) => w.write_all(bytes)?,
+        }
+        w.write_all(b"\n")
+    }
+
     #[allow(dead_code)]
     pub fn apply_num<F>(&mut self, f: F) -> Result<Self, DCError>
     where
@@ -76,6 +94,7 @@ impl MemoryCell {
         }
     }
 
+    #[allow(dead_code)]
     pub fn apply_num_opt<F>(&mut self, f: F) -> Result<Self, DCError>
     where
         F: Fn(&BigDecimal) -> Option<BigDecimal>,
@@ -98,23 +117,201 @@ fn test_to_string_with_base() {
 
 fn to_string_radix(n: &BigDecimal, radix: u32) -> String {
     if radix == 10 {
-        return format!("{}", n);
+        return to_string_decimal(n);
     }
-    let (bigint, exp) = n.as_bigint_and_exponent();
-    let mut s = bigint.to_str_radix(radix);
-    assert!(exp >= 0);
-    if exp > 0 {
-        let dot_insertion = s.len() - exp as usize;
-        s.insert(dot_insertion, '.');
+    if radix > 16 {
+        return to_string_big_radix(n, radix);
+    }
+    if n.is_zero() {
+        return "0".to_string();
+    }
+    let (integer, fraction) = split_digits(n, radix);
+    let mut s = String::new();
+    if n.is_negative() {
+        s.push('-');
+    }
+    s.extend(integer.into_iter().map(small_radix_digit));
+    if n.as_bigint_and_exponent().1 > 0 {
+        s.push('.');
+        s.extend(fraction.into_iter().map(small_radix_digit));
     }
     s
 }
 
+// dc prints all the digits of the scale, without a leading zero before the
+// dot and never in exponential notation
+fn to_string_decimal(n: &BigDecimal) -> String {
+    if n.is_zero() {
+        return "0".to_string();
+    }
+    let (bigint, scale) = n.as_bigint_and_exponent();
+    let mut digits = bigint.abs().to_str_radix(10);
+    if scale <= 0 {
+        digits.push_str(&"0".repeat(-scale as usize));
+    } else {
+        let scale = scale as usize;
+        if digits.len() < scale {
+            let padding = "0".repeat(scale - digits.len());
+            digits.insert_str(0, &padding);
+        }
+        let dot_insertion = digits.len() - scale;
+        digits.insert(dot_insertion, '.');
+    }
+    if n.is_negative() {
+        digits.insert(0, '-');
+    }
+    digits
+}
+
+#[test]
+fn test_to_string_decimal() {
+    let to_string = |s: &str| to_string_decimal(&BigDecimal::from_str(s).unwrap());
+    assert_eq!(".5", to_string("0.5"));
+    assert_eq!("-.5", to_string("-0.5"));
+    assert_eq!(".005", to_string("0.005"));
+    assert_eq!("1.50", to_string("1.50"));
+    assert_eq!("-12.000", to_string("-12.000"));
+    assert_eq!("0", to_string("0.000"));
+    assert_eq!("1000000000000000000000000", to_string("1e24"));
+    assert_eq!(".000000000000000000000001", to_string("1e-24"));
+}
+
+// dc uses upper case letters for the digits above 9
+fn small_radix_digit(digit: u32) -> char {
+    std::char::from_digit(digit, 16)
+        .expect("digits are smaller than the radix")
+        .to_ascii_uppercase()
+}
+
+#[test]
+fn test_to_string_small_radix() {
+    let to_string = |s: &str, radix| to_string_radix(&BigDecimal::from_str(s).unwrap(), radix);
+    assert_eq!(".8", to_string(".5", 16));
+    assert_eq!("-.8", to_string("-.5", 16));
+    assert_eq!("FF.C0", to_string("255.75", 16));
+    assert_eq!("-11", to_string("-9", 8));
+    assert_eq!("1.0001100", to_string("1.10", 2));
+    assert_eq!("1100100", to_string("1e2", 2));
+    assert_eq!("0", to_string("0.000", 16));
+}
+
+// dc breaks long lines after `line_length - 1` characters with a `\`
+fn wrap_line(s: &str, line_length: usize) -> String {
+    if line_length < 2 {
+        return s.to_string();
+    }
+    let mut wrapped = String::with_capacity(s.len() + 2 * s.len() / (line_length - 1));
+    for (index, c) in s.chars().enumerate() {
+        if index > 0 && index % (line_length - 1) == 0 {
+            wrapped.push_str("\\\n");
+        }
+        wrapped.push(c);
+    }
+    wrapped
+}
+
+#[test]
+fn test_wrap_line() {
+    assert_eq!("12345", wrap_line("12345", 0));
+    assert_eq!("12345", wrap_line("12345", 6));
+    assert_eq!("1234\\\n5", wrap_line("12345", 5));
+    assert_eq!("12\\\n34\\\n5", wrap_line("12345", 3));
+}
+
+// the digits of a non negative integer, most significant first (none for 0)
+fn integer_digits(n: &bigint::BigInt, radix: u32) -> Vec<u32> {
+    let radix = bigint::BigInt::from(radix);
+    let mut n = n.clone();
+    let mut digits = Vec::new();
+    while !n.is_zero() {
+        let (quotient, digit) = n.div_rem(&radix);
+        digits.push(digit.to_u32().expect("digits are smaller than the radix"));
+        n = quotient;
+    }
+    digits.reverse();
+    digits
+}
+
+// dc's algorithm for the digits of `fraction / 10^scale` (a non negative
+// number smaller than 1): multiply by the radix and take the integer part,
+// until the radix power has more digits than the scale
+fn fraction_digits(fraction: &bigint::BigInt, scale: u64, radix: u32) -> Vec<u32> {
+    let one = num::pow(bigint::BigInt::from(10), scale as usize);
+    let mut fraction = fraction.clone();
+    let mut radix_power = bigint::BigInt::from(1);
+    let mut digits = Vec::new();
+    while radix_power < one {
+        fraction = fraction * radix;
+        let (digit, rest) = fraction.div_rem(&one);
+        digits.push(digit.to_u32().expect("digits are smaller than the radix"));
+        fraction = rest;
+        radix_power = radix_power * radix;
+    }
+    digits
+}
+
+// the integer and fractional digits of the absolute value of `n`
+fn split_digits(n: &BigDecimal, radix: u32) -> (Vec<u32>, Vec<u32>) {
+    let (bigint, scale) = n.as_bigint_and_exponent();
+    let bigint = bigint.abs();
+    if scale <= 0 {
+        let bigint = bigint * num::pow(bigint::BigInt::from(10), -scale as usize);
+        return (integer_digits(&bigint, radix), Vec::new());
+    }
+    let scale = scale as u64;
+    let (integer, fraction) = bigint.div_rem(&num::pow(bigint::BigInt::from(10), scale as usize));
+    (
+        integer_digits(&integer, radix),
+        fraction_digits(&fraction, scale, radix),
+    )
+}
+
+// GNU dc prints each digit of radixes above 16 as a zero padded decimal
+// number, preceded by a space (but for the first fractional one)
+fn to_string_big_radix(n: &BigDecimal, radix: u32) -> String {
+    use std::fmt::Write;
+
+    if n.is_zero() {
+        return "0".to_string();
+    }
+    let width = (radix - 1).to_string().len();
+    let (integer, fraction) = split_digits(n, radix);
+    let mut s = String::new();
+    if n.is_negative() {
+        s.push('-');
+    }
+    for digit in integer {
+        write!(s, " {:0width$}", digit, width = width).expect("writing to a string");
+    }
+    if n.as_bigint_and_exponent().1 > 0 {
+        s.push('.');
+        for (index, digit) in fraction.into_iter().enumerate() {
+            if index > 0 {
+                s.push(' ');
+            }
+            write!(s, "{:0width$}", digit, width = width).expect("writing to a string");
+        }
+    }
+    s
+}
+
+#[test]
+fn test_to_string_big_radix() {
+    let to_string = |s: &str, radix| to_string_big_radix(&BigDecimal::from_str(s).unwrap(), radix);
+    assert_eq!(" 01 01 01", to_string("3661", 60));
+    assert_eq!(" 001 234", to_string("1234", 1000));
+    assert_eq!("- 01 00", to_string("-17", 17));
+    assert_eq!(" 01.30", to_string("1.5", 60));
+    assert_eq!(".30", to_string(".5", 60));
+    assert_eq!(" 255.128", to_string("255.50", 256));
+    assert_eq!("0", to_string("0.00", 100));
+}
+
 impl fmt::Display for MemoryCell {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             &MemoryCell::Num(ref n) => f.write_str(&to_string_radix(n, 10)),
-            &MemoryCell::Str(ref txt) => write!(f, "[{}]", &str::from_utf8(txt).expect("utf8 error")),
+            &MemoryCell::Str(ref txt) => write!(f, "[{}]", String::from_utf8_lossy(txt)),
         }
     }
 }
@@ -158,28 +355,78 @@ pub enum DCError {
     NonStringValue,
     NumParseError,
     InternalConversionError,
+    RegisterEmpty(Register),
+    StackRegisterEmpty(Register),
+    InvalidArrayIndex,
+    DivideByZero,
+    ExponentTooLarge,
+    RemainderByZero,
+    NegativeExponent,
+    NonZeroScaleInBase,
+    NonZeroScaleInExponent,
+    NonZeroScaleInModulus,
+    SqrtOfNegative,
 }
-static STACK_EMPTY: &'static str = "stack empty";
-static NON_NUMERIC_VALUE: &'static str = "non numeric value";
-static NON_STRING_VALUE: &'static str = "non string value";
-static NUM_PARSE_ERROR: &'static str = "bytes do not represent a number";
-static INTERNAL_CONVERSION_ERROR: &'static str = "internal conversion error";
+static STACK_EMPTY: &str = "stack empty";
+static NON_NUMERIC_VALUE: &str = "non-numeric value";
+static NON_STRING_VALUE: &str = "non string value";
+static NUM_PARSE_ERROR: &str = "bytes do not represent a number";
+static INTERNAL_CONVERSION_ERROR: &str = "internal conversion error";
+static REGISTER_EMPTY: &str = "register is empty";
+static STACK_REGISTER_EMPTY: &str = "stack register is empty";
+static INVALID_ARRAY_INDEX: &str = "array index must be a nonnegative integer";
+static DIVIDE_BY_ZERO: &str = "divide by zero";
+static EXPONENT_TOO_LARGE: &str = "exponent too large in raise";
+static REMAINDER_BY_ZERO: &str = "remainder by zero";
+static NEGATIVE_EXPONENT: &str = "negative exponent";
+static NON_ZERO_SCALE_IN_BASE: &str = "non-zero scale in base";
+static NON_ZERO_SCALE_IN_EXPONENT: &str = "non-zero scale in exponent";
+static NON_ZERO_SCALE_IN_MODULUS: &str = "non-zero scale in modulus";
+static SQRT_OF_NEGATIVE: &str = "square root of negative number";
 
 impl DCError {
-    pub fn message(&self) -> &'static str {
+    pub fn message(&self) -> &str {
         match self {
-            &DCError::StackEmpty => &STACK_EMPTY,
-            &DCError::NonNumericValue => &NON_NUMERIC_VALUE,
-            &DCError::NonStringValue => &NON_STRING_VALUE,
-            &DCError::NumParseError => &NUM_PARSE_ERROR,
-            &DCError::InternalConversionError => &INTERNAL_CONVERSION_ERROR,
+            &DCError::StackEmpty => STACK_EMPTY,
+            &DCError::NonNumericValue => NON_NUMERIC_VALUE,
+            &DCError::NonStringValue => NON_STRING_VALUE,
+            &DCError::NumParseError => NUM_PARSE_ERROR,
+            &DCError::InternalConversionError => INTERNAL_CONVERSION_ERROR,
+            &DCError::RegisterEmpty(..) => REGISTER_EMPTY,
+            &DCError::StackRegisterEmpty(..) => STACK_REGISTER_EMPTY,
+            &DCError::InvalidArrayIndex => INVALID_ARRAY_INDEX,
+            &DCError::DivideByZero => DIVIDE_BY_ZERO,
+            &DCError::ExponentTooLarge => EXPONENT_TOO_LARGE,
+            &DCError::RemainderByZero => REMAINDER_BY_ZERO,
+            &DCError::NegativeExponent => NEGATIVE_EXPONENT,
+            &DCError::NonZeroScaleInBase => NON_ZERO_SCALE_IN_BASE,
+            &DCError::NonZeroScaleInExponent => NON_ZERO_SCALE_IN_EXPONENT,
+            &DCError::NonZeroScaleInModulus => NON_ZERO_SCALE_IN_MODULUS,
+            &DCError::SqrtOfNegative => SQRT_OF_NEGATIVE,
         }
     }
 }
 
+// mimics dc_show_id in GNU dc: 'a' (0141)
+struct RegisterId(Register);
+
+impl fmt::Display for RegisterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "'{}' ({:04o})", self.0 as char, self.0)
+    }
+}
+
 impl fmt::Display for DCError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{}", self.message())?;
+        match self {
+            &DCError::RegisterEmpty(register) => {
+                write!(f, "register {} is empty", RegisterId(register))?
+            }
+            &DCError::StackRegisterEmpty(register) => {
+                write!(f, "stack register {} is empty", RegisterId(register))?
+            }
+            error => write!(f, "{}", error.message())?,
+        }
         Ok(())
     }
 }
@@ -210,11 +457,48 @@ macro_rules! dcstack {
     })
 }
 
-fn make_big_decimal(digits: &[u8], radix: u32) -> Result<BigDecimal, DCError> {
-    if let Some(n) = bigint::BigInt::parse_bytes(digits, radix) {
+fn digit_value(digit: u8) -> Result<u32, DCError> {
+    match digit {
+        b'0'..=b'9' => Ok((digit - b'0') as u32),
+        b'A'..=b'F' => Ok((digit - b'A') as u32 + 10),
+        _ => Err(DCError::NumParseError),
+    }
+}
+
+// like dc, digits are accepted even if they are not smaller than the radix
+fn accumulate_digits(
+    mut n: bigint::BigInt,
+    digits: &[u8],
+    radix: u32,
+) -> Result<bigint::BigInt, DCError> {
+    for &digit in digits {
+        n = n * radix + digit_value(digit)?;
+    }
+    Ok(n)
+}
+
+/// Parses a dc number literal in the given radix. The value keeps as many
+/// (decimal) fractional digits as there are fractional digits in the literal,
+/// truncating the rest as dc does.
+fn make_big_decimal(
+    integer: &[u8],
+    fraction: &[u8],
+    radix: u32,
+) -> Result<BigDecimal, DCError> {
+    // single digits have their face value whatever the radix
+    if integer.len() == 1 && fraction.is_empty() {
+        return Ok(BigDecimal::from(digit_value(integer[0])?));
+    }
+
+    let n = accumulate_digits(bigint::BigInt::from(0), integer, radix)?;
+    if fraction.is_empty() {
         return Ok(BigDecimal::new(n, 0));
     }
-    Err(DCError::NumParseError)
+    let n = accumulate_digits(n, fraction, radix)?;
+    let scale = fraction.len() as u64;
+    let numerator = n * num::pow(bigint::BigInt::from(10), scale as usize);
+    let denominator = num::pow(bigint::BigInt::from(radix), fraction.len());
+    Ok(BigDecimal::new(numerator / denominator, scale as i64))
 }
 
 #[derive(Debug)]
@@ -244,63 +528,25 @@ impl DCStack {
 
     pub fn push_bytes_as_num(
         &mut self,
+        sign: Sign,
         integer: &[u8],
         fraction: &[u8],
         radix: u32,
     ) -> Result<(), DCError> {
-        let scale = fraction.len();
-
-        if cfg!(feature = "parse_all_bases") {
-            println!(
-                "slice {} {} {}",
-                radix,
-                String::from_utf8(Vec::from(integer)).unwrap(),
-                String::from_utf8(Vec::from(fraction)).unwrap()
-            );
-            let mut integer = make_big_decimal(integer, radix)?;
-            if scale > 0 {
-                let mut fraction = make_big_decimal(fraction, radix)?;
-                let radix = BigDecimal::from(radix);
-                for _i in 1..scale {
-                    fraction = fraction / &radix;
-                }
-                integer += fraction;
-            }
-            self.push(MemoryCell::from(integer));
-            Ok(())
-        } else {
-            // TODO: 1. find a way to avoid copy and similar ops
-            // 2. We can easily support other bases without digits
-            // 3. it is a much better format to have one single buffer and
-            //    the position, since it gives us the top flexibility
-            // 4. We support "everything"
-
-            if radix != 10 {
-                panic!("only supporting base 10");
-            }
-
-            // TODO: now we must copy memory, but being slightly smarter with
-            // the parser would avoid it (e.g., put all the bytes in a single slice)
-            let mut v = Vec::with_capacity(integer.len() + fraction.len() + 1);
-            v.extend_from_slice(integer);
-            if !fraction.is_empty() {
-                v.push(b'.');
-                v.extend_from_slice(fraction);
-            }
-
-            self.stack.push(MemoryCell::Num(
-                BigDecimal::parse_bytes(&v, 10).expect("number should have been valid"),
-            ));
-            Ok(())
-        }
+        let n = make_big_decimal(integer, fraction, radix)?;
+        self.push(MemoryCell::Num(match sign {
+            Sign::Positive => n,
+            Sign::Negative => -n,
+        }));
+        Ok(())
     }
 
-    pub fn write_to<W>(&self, w: &mut W, radix: u32) -> Result<(), io::Error>
+    pub fn write_to<W>(&self, w: &mut W, radix: u32, line_length: usize) -> Result<(), io::Error>
     where
         W: io::Write,
     {
         for item in self.stack.iter().rev() {
-            writeln!(w, "{}", item.to_str_radix(radix))?;
+            item.write_to(w, radix, line_length)?;
         }
         Ok(())
     }
@@ -337,6 +583,68 @@ impl DCStack {
         }
     }
 
+    /// Like `binary_apply_and_consume_tos`, but `f` may fail: in that case the
+    /// stack is left untouched.
+    pub fn binary_try_apply_and_consume_tos<F>(&mut self, f: F) -> Result<(), DCError>
+    where
+        F: Fn(&BigDecimal, &BigDecimal) -> Result<BigDecimal, DCError>,
+    {
+        let len = self.len();
+        if len < 2 {
+            return Err(DCError::StackEmpty);
+        }
+
+        let result = match (&self.stack[len - 2], &self.stack[len - 1]) {
+            (MemoryCell::Num(lhs), MemoryCell::Num(rhs)) => f(lhs, rhs)?,
+            _ => return Err(DCError::NonNumericValue),
+        };
+        self.stack.truncate(len - 2);
+        self.stack.push(MemoryCell::Num(result));
+        Ok(())
+    }
+
+    /// Like `binary_try_apply_and_consume_tos`, but pushes two results.
+    pub fn binary_try_apply_and_consume_tos_pair<F>(&mut self, f: F) -> Result<(), DCError>
+    where
+        F: Fn(&BigDecimal, &BigDecimal) -> Result<(BigDecimal, BigDecimal), DCError>,
+    {
+        let len = self.len();
+        if len < 2 {
+            return Err(DCError::StackEmpty);
+        }
+
+        let (first, second) = match (&self.stack[len - 2], &self.stack[len - 1]) {
+            (MemoryCell::Num(lhs), MemoryCell::Num(rhs)) => f(lhs, rhs)?,
+            _ => return Err(DCError::NonNumericValue),
+        };
+        self.stack.truncate(len - 2);
+        self.stack.push(MemoryCell::Num(first));
+        self.stack.push(MemoryCell::Num(second));
+        Ok(())
+    }
+
+    /// Applies `f` to the three topmost numbers (in push order), replacing
+    /// them with the result. The stack is left untouched on failure.
+    pub fn ternary_try_apply_and_consume_tos<F>(&mut self, f: F) -> Result<(), DCError>
+    where
+        F: Fn(&BigDecimal, &BigDecimal, &BigDecimal) -> Result<BigDecimal, DCError>,
+    {
+        let len = self.len();
+        if len < 3 {
+            return Err(DCError::StackEmpty);
+        }
+
+        let result = match (&self.stack[len - 3], &self.stack[len - 2], &self.stack[len - 1]) {
+            (MemoryCell::Num(first), MemoryCell::Num(second), MemoryCell::Num(third)) => {
+                f(first, second, third)?
+            }
+            _ => return Err(DCError::NonNumericValue),
+        };
+        self.stack.truncate(len - 3);
+        self.stack.push(MemoryCell::Num(result));
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn apply_tos_num<F>(&mut self, f: F) -> Result<(), DCError>
     where
@@ -350,6 +658,7 @@ impl DCStack {
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn apply_tos_num_opt<F>(&mut self, f: F) -> Result<(), DCError>
     where
         F: Fn(&BigDecimal) -> Option<BigDecimal>,
@@ -362,6 +671,19 @@ impl DCStack {
         Ok(())
     }
 
+    /// Replaces the top of the stack with `f` applied to it, unless `f` fails.
+    pub fn try_apply_tos_num<F>(&mut self, f: F) -> Result<(), DCError>
+    where
+        F: Fn(&BigDecimal) -> Result<BigDecimal, DCError>,
+    {
+        let result = match *self.peek()? {
+            MemoryCell::Num(ref n) => f(n)?,
+            MemoryCell::Str(..) => return Err(DCError::NonNumericValue),
+        };
+        *self.peek_mut()? = MemoryCell::Num(result);
+        Ok(())
+    }
+
     pub fn push_str(&mut self, item: &[u8]) {
         self.push(MemoryCell::Str(Vec::from(item)))
     }
@@ -389,6 +711,19 @@ impl DCStack {
         }
     }
 
+    /// Pops the two topmost numbers as (tos, second). Like dc, nothing is
+    /// consumed if there are not enough values, while non numeric values
+    /// are discarded.
+    pub fn pop_num_pair(&mut self) -> Result<(BigDecimal, BigDecimal), DCError> {
+        if self.len() < 2 {
+            return Err(DCError::StackEmpty);
+        }
+        match (self.pop()?, self.pop()?) {
+            (MemoryCell::Num(tos), MemoryCell::Num(second)) => Ok((tos, second)),
+            _ => Err(DCError::NonNumericValue),
+        }
+    }
+
     pub fn pop_str(&mut self) -> Result<Vec<u8>, DCError> {
         match self.pop()? {
             MemoryCell::Num(n) => {
@@ -491,6 +826,23 @@ fn test_push_pop() {
     assert_eq!(BigDecimal::from_str("10.22").expect("was a number"), bd);
 }
 
+#[test]
+fn test_pop_num_pair() {
+    let mut s = dcstack_num![1, 2];
+    assert_eq!(
+        Ok((BigDecimal::from(2), BigDecimal::from(1))),
+        s.pop_num_pair()
+    );
+    assert!(s.is_empty());
+}
+
+#[test]
+fn test_pop_num_pair_one() {
+    let mut s = dcstack_num![1];
+    assert_eq!(Err(DCError::StackEmpty), s.pop_num_pair());
+    assert_eq!(1, s.len());
+}
+
 #[test]
 fn test_dup() {
     let mut s = dcstack_num!(10);
@@ -542,6 +894,26 @@ fn test_clear_empty() {
     assert_eq!(0, s.len());
 }
 
+#[test]
+fn test_make_big_decimal() {
+    let parse = |integer: &str, fraction: &str, radix| {
+        make_big_decimal(integer.as_bytes(), fraction.as_bytes(), radix)
+    };
+    assert_eq!(Ok(BigDecimal::from(255)), parse("FF", "", 16));
+    assert_eq!(Ok(BigDecimal::from(5)), parse("101", "", 2));
+    assert_eq!(Ok(BigDecimal::from(15)), parse("F", "", 2));
+    // digits beyond the radix are accepted
+    assert_eq!(Ok(BigDecimal::from(21)), parse("1B", "", 10));
+    assert_eq!(Ok(BigDecimal::from(1.625)), parse("1", "101", 2));
+    assert_eq!(Ok(BigDecimal::from(10.6)), parse("A", "A", 16));
+    assert_eq!(Ok(BigDecimal::from(0.5)), parse("", "8", 16));
+    assert_eq!(
+        (bigint::BigInt::from(1230), 3),
+        parse("1", "230", 10).unwrap().as_bigint_and_exponent()
+    );
+    assert_eq!(Ok(BigDecimal::from(0)), parse("", "", 10));
+}
+
 #[test]
 fn test_behavior() {
     let n = bigint::BigInt::parse_bytes(b"A", 16).expect("BigInt expecting success");
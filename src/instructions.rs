@@ -19,10 +19,17 @@ pub enum RegisterOperationType {
     GetArray,
 }
 
+// dc writes negative literals with a leading `_`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sign {
+    Positive,
+    Negative,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Instruction<'a> {
     Nop,
-    Num(&'a [u8], &'a [u8]),
+    Num(Sign, &'a [u8], &'a [u8]),
     Str(&'a [u8]),
     // print
     PrintLN,
@@ -75,7 +82,10 @@ impl<'a> fmt::Display for Instruction<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             &Instruction::Nop => f.write_str("\0"),
-            &Instruction::Num(ref integer, ref fractional) => {
+            &Instruction::Num(sign, ref integer, ref fractional) => {
+                if sign == Sign::Negative {
+                    f.write_str("_")?;
+                }
                 if fractional.len() > 0 {
                     write!(f, "{}.{}", allocate_str(integer), allocate_str(fractional))
                 }  else {
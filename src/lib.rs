@@ -1,11 +1,18 @@
 extern crate bigdecimal;
 extern crate num;
 extern crate num_bigint;
+#[cfg(feature = "repl")]
+extern crate rustyline;
 
 mod instructions;
 mod parse;
 #[macro_use]
 mod dcstack;
+mod numeric;
+mod parameters;
+mod registers;
+pub mod repl;
+pub mod system;
 pub mod vm;
 
 use std::io::Write;
@@ -16,9 +23,35 @@ use std::iter::Iterator;
 use std::ffi::OsStr;
 use std::ops::Deref;
 
+static USAGE: &str = "Usage: rdc [OPTION]... [FILE]...
+Runs the expressions and files in the order they are given, or the standard
+input when there are none.
+
+  -e, --expression=EXPR    evaluate expression
+  -f, --file=FILE          evaluate contents of file
+  -h, --help               display this help and exit
+  -V, --version            output version information and exit
+      --strict             stop at the first error
+      --prompt=MODE        what the interactive prompt shows: plain, depth
+                           (of the stack) or top (of the stack), with the
+                           repl feature
+
+A FILE of - is the standard input, -- ends the options. On a terminal, the
+standard input is an interactive session, with history in ~/.rdc_history, if
+rdc was built with the repl feature; otherwise each line runs once it is read.
+The exit status is 1 if a file cannot be read or the program reports errors.
+";
+
+/// The exit status of a successful run.
+pub const EXIT_SUCCESS: i32 = 0;
+/// The exit status when options are invalid or errors were reported.
+pub const EXIT_FAILURE: i32 = 1;
+
+#[derive(Debug, PartialEq)]
 enum ProgramSource<ProgramText, ProgramPath> {
     Text(ProgramText),
     File(ProgramPath),
+    Stdin,
 }
 
 impl<ProgramText, ProgramPath> ProgramSource<ProgramText, ProgramPath>
@@ -26,6 +59,7 @@ where
     ProgramText: Deref<Target = str>,
     ProgramPath: AsRef<OsStr>,
 {
+    // the standard input belongs to the VM, which reads it itself
     fn into_bytes<'a>(self, buffer: &mut Vec<u8>) -> Result<usize, std::io::Error> {
         match self {
             ProgramSource::Text(text_str) => {
@@ -36,82 +70,574 @@ where
                 let mut file = File::open(path)?;
                 return Ok(file.read_to_end(buffer)?);
             }
+            ProgramSource::Stdin => Ok(0),
+        }
+    }
+}
+
+// what the command line asks for
+#[derive(Debug, PartialEq)]
+enum Invocation<ProgramText, ProgramPath> {
+    Run(Vec<ProgramSource<ProgramText, ProgramPath>>),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+enum ArgsError {
+    InvalidOption(char),
+    UnrecognizedOption(String),
+    MissingArgument(char),
+    MissingLongArgument(String),
+    InvalidArgument(String, String),
+    Unsupported(String),
+}
+
+// the messages of getopt, which dc uses
+impl std::fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            ArgsError::InvalidOption(option) => write!(f, "invalid option -- '{}'", option),
+            ArgsError::UnrecognizedOption(ref option) => {
+                write!(f, "unrecognized option '{}'", option)
+            }
+            ArgsError::MissingArgument(option) => {
+                write!(f, "option requires an argument -- '{}'", option)
+            }
+            ArgsError::MissingLongArgument(ref option) => {
+                write!(f, "option '{}' requires an argument", option)
+            }
+            ArgsError::InvalidArgument(ref option, ref argument) => {
+                write!(f, "invalid argument '{}' for '{}'", argument, option)
+            }
+            ArgsError::Unsupported(ref option) => {
+                write!(f, "option '{}' requires rdc built with the repl feature", option)
+            }
         }
     }
 }
 
+fn option_source<ProgramText, ProgramPath, S>(
+    option: char,
+    argument: S,
+) -> ProgramSource<ProgramText, ProgramPath>
+where
+    S: AsRef<str> + Into<ProgramText> + Into<ProgramPath>,
+{
+    if option == 'e' {
+        ProgramSource::Text(argument.into())
+    } else {
+        file_source(argument)
+    }
+}
+
+fn file_source<ProgramText, ProgramPath, S>(file: S) -> ProgramSource<ProgramText, ProgramPath>
+where
+    S: AsRef<str> + Into<ProgramText> + Into<ProgramPath>,
+{
+    if file.as_ref() == "-" {
+        ProgramSource::Stdin
+    } else {
+        ProgramSource::File(file.into())
+    }
+}
+
 fn parse_args<ProgramText, ProgramPath, I, S>(
     mut args: I,
-) -> Vec<ProgramSource<ProgramText, ProgramPath>>
+    options: &mut Options,
+) -> Result<Invocation<ProgramText, ProgramPath>, ArgsError>
 where
     I: Iterator<Item = S>,
-    S: AsRef<str> + Into<ProgramText> + Into<ProgramPath>,
+    S: AsRef<str> + Into<ProgramText> + Into<ProgramPath> + From<String>,
 {
     let mut program_sources: Vec<ProgramSource<ProgramText, ProgramPath>> = Vec::new();
-    let mut positional_program_sources: Vec<ProgramSource<ProgramText, ProgramPath>> = Vec::new();
+    let mut options_ended = false;
 
     while let Some(arg) = args.next() {
-        match arg.as_ref() {
-            "-e" | "--expression" => match args.next() {
-                Some(text) => program_sources.push(ProgramSource::Text(text.into())),
-                None => print_help(1),
-            },
-            "-f" | "--file" => match args.next() {
-                Some(file) => program_sources.push(ProgramSource::File(file.into())),
-                None => print_help(1),
-            },
-            "-h" | "--help" => print_help(0),
-            "-v" | "--version" => print_version(0),
-            _ => {
-                positional_program_sources.push(ProgramSource::File(arg.into()));
+        if options_ended || arg.as_ref() == "-" || !arg.as_ref().starts_with('-') {
+            program_sources.push(file_source(arg));
+            continue;
+        }
+        if arg.as_ref() == "--" {
+            options_ended = true;
+            continue;
+        }
+        if arg.as_ref().starts_with("--") {
+            let (name, value) = match arg.as_ref().find('=') {
+                Some(index) => (
+                    arg.as_ref()[..index].to_string(),
+                    Some(arg.as_ref()[index + 1..].to_string()),
+                ),
+                None => (arg.as_ref().to_string(), None),
+            };
+            let option = match name.as_str() {
+                "--expression" => 'e',
+                "--file" => 'f',
+                "--help" if value.is_none() => return Ok(Invocation::Help),
+                "--strict" if value.is_none() => {
+                    options.strict = true;
+                    continue;
+                }
+                "--prompt" if !cfg!(feature = "repl") => {
+                    return Err(ArgsError::Unsupported(name))
+                }
+                "--prompt" => {
+                    let argument = match value {
+                        Some(value) => value,
+                        None => args
+                            .next()
+                            .ok_or_else(|| ArgsError::MissingLongArgument(name.clone()))?
+                            .as_ref()
+                            .to_string(),
+                    };
+                    options.prompt = repl::Prompt::from_name(&argument)
+                        .ok_or(ArgsError::InvalidArgument(name, argument))?;
+                    continue;
+                }
+                "--version" if value.is_none() => return Ok(Invocation::Version),
+                _ => return Err(ArgsError::UnrecognizedOption(arg.as_ref().to_string())),
+            };
+            let argument = match value {
+                Some(value) => S::from(value),
+                None => args.next().ok_or(ArgsError::MissingLongArgument(name))?,
+            };
+            program_sources.push(option_source(option, argument));
+            continue;
+        }
+        // in a group of short options, like -Ve1p, each option ends the group:
+        // -h and -V stop the parsing, -e and -f take the rest as argument
+        let mut flags = arg.as_ref()[1..].chars();
+        let option = flags.next().expect("- alone is the standard input");
+        match option {
+            'h' => return Ok(Invocation::Help),
+            'v' | 'V' => return Ok(Invocation::Version),
+            'e' | 'f' => {
+                let argument = match flags.as_str() {
+                    "" => args.next().ok_or(ArgsError::MissingArgument(option))?,
+                    rest => S::from(rest.to_string()),
+                };
+                program_sources.push(option_source(option, argument));
             }
+            _ => return Err(ArgsError::InvalidOption(option)),
+        }
+    }
+    if program_sources.is_empty() {
+        program_sources.push(ProgramSource::Stdin);
+    }
+    Ok(Invocation::Run(program_sources))
+}
+
+/// Settings of a run: the environment provides some of them, the command
+/// line can change the others.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The width at which numbers are wrapped, 0 disables wrapping.
+    pub line_length: usize,
+    /// Whether the first error stops the execution (`--strict`).
+    pub strict: bool,
+    /// Whether the standard input is a terminal, to read it interactively.
+    pub interactive: bool,
+    /// What the interactive prompt shows (`--prompt`).
+    pub prompt: repl::Prompt,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            line_length: vm::DEFAULT_LINE_LENGTH,
+            strict: false,
+            interactive: false,
+            prompt: repl::Prompt::Plain,
         }
     }
-    program_sources.extend(positional_program_sources);
-    program_sources
 }
 
-pub fn dc<'a, I, S, W, E>(args: I, stdout: W, stderr: E) -> (W, E)
+pub fn dc<'a, I, S, R, W, E>(args: I, stdin: R, stdout: W, stderr: E) -> (W, E)
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str> + Into<String> + PartialEq<&'a str>,
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    let (stdout, stderr, _) = dc_with_options(args, stdin, stdout, stderr, &Options::default());
+    (stdout, stderr)
+}
+
+pub fn dc_with_options<'a, I, S, R, W, E>(
+    args: I,
+    stdin: R,
+    mut stdout: W,
+    mut stderr: E,
+    options: &Options,
+) -> (W, E, i32)
 where
     I: Iterator<Item = S>,
     S: AsRef<str> + Into<String> + PartialEq<&'a str>,
+    R: BufRead,
     W: Write,
     E: Write,
 {
-    let program_sources = parse_args(args);
-    dc_exec_program_sources(program_sources, stdout, stderr)
+    let mut options = options.clone();
+    match parse_args::<String, String, _, _>(args.map(Into::into), &mut options) {
+        Ok(Invocation::Run(program_sources)) => {
+            dc_exec_program_sources(program_sources, stdin, stdout, stderr, &options)
+        }
+        Ok(Invocation::Help) => {
+            let status = match stdout.write_all(USAGE.as_bytes()) {
+                Ok(()) => EXIT_SUCCESS,
+                Err(..) => EXIT_FAILURE,
+            };
+            (stdout, stderr, status)
+        }
+        Ok(Invocation::Version) => {
+            let status = match writeln!(stdout, "rdc {}", env!("CARGO_PKG_VERSION")) {
+                Ok(()) => EXIT_SUCCESS,
+                Err(..) => EXIT_FAILURE,
+            };
+            (stdout, stderr, status)
+        }
+        Err(error) => {
+            let _ = writeln!(stderr, "dc: {}", error);
+            let _ = stderr.write_all(USAGE.as_bytes());
+            (stdout, stderr, EXIT_FAILURE)
+        }
+    }
 }
 
-fn dc_exec_program_sources<ProgramText, ProgramPath, I, W, E>(
+#[cfg_attr(not(feature = "repl"), allow(unused_variables))]
+fn run_program_source<ProgramText, ProgramPath, R, W, E>(
+    vm: &mut vm::VM<R, W, E>,
+    program_source: ProgramSource<ProgramText, ProgramPath>,
+    options: &Options,
+) -> std::io::Result<vm::ReturnState>
+where
+    ProgramPath: AsRef<OsStr>,
+    ProgramText: Deref<Target = str>,
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    let mut source_code = Vec::new();
+    let read = match program_source {
+        #[cfg(feature = "repl")]
+        ProgramSource::Stdin if options.interactive => return repl::run(vm, options.prompt),
+        // without the line editor a terminal still gets the output of each
+        // line as soon as it is typed
+        ProgramSource::Stdin => return vm.execute_input(),
+        program_source => program_source.into_bytes(&mut source_code),
+    };
+    match read {
+        Ok(..) => vm.execute(&source_code),
+        Err(error) => vm.report_error(error).map(|_| vm::ReturnState::Done),
+    }
+}
+
+fn dc_exec_program_sources<ProgramText, ProgramPath, I, R, W, E>(
     program_sources: I,
+    stdin: R,
     stdout: W,
     stderr: E,
-) -> (W, E)
+    options: &Options,
+) -> (W, E, i32)
 where
     I: IntoIterator<Item = ProgramSource<ProgramText, ProgramPath>>,
     ProgramPath: AsRef<OsStr>,
     ProgramText: Deref<Target = str>,
+    R: BufRead,
     W: Write,
     E: Write,
 {
-    let mut vm = vm::VM::new(stdout, stderr);
+    let mut vm = vm::VM::new(stdin, stdout, stderr);
+    vm.set_line_length(options.line_length);
+    vm.set_strict(options.strict);
+    // if even the errors cannot be written, there is nothing left to do
+    let mut io_failed = false;
     for program_source in program_sources {
-        let mut source_code = Vec::new();
-        if let Err(error) = program_source.into_bytes(&mut source_code) {
-            eprintln!("dc: {}", error);
-            continue;
+        match run_program_source(&mut vm, program_source, options) {
+            // q ends the program, the sources after it are not run
+            Ok(state) if state.quits() => break,
+            Ok(..) => {}
+            Err(error) => {
+                if vm.report_error(error).is_err() {
+                    io_failed = true;
+                }
+            }
         }
-        if let Err(error) = vm.execute(&source_code) {
-            eprintln!("dc: {}", error);
+        if io_failed || (options.strict && vm.errors() > 0) {
+            break;
         }
     }
-    vm.sinks()
+    let status = if io_failed || vm.errors() > 0 {
+        EXIT_FAILURE
+    } else {
+        EXIT_SUCCESS
+    };
+    let (stdout, stderr) = vm.sinks();
+    (stdout, stderr, status)
+}
+
+#[cfg(test)]
+fn test_parse_args(args: &[&str]) -> Result<Invocation<String, String>, ArgsError> {
+    parse_args(args.iter().map(|arg| arg.to_string()), &mut Options::default())
+}
+
+#[test]
+fn test_parse_args_order() {
+    assert_eq!(
+        Ok(Invocation::Run(vec![
+            ProgramSource::File("a.dc".to_string()),
+            ProgramSource::Text("1p".to_string()),
+            ProgramSource::File("b.dc".to_string()),
+            ProgramSource::Stdin,
+        ])),
+        test_parse_args(&["a.dc", "-e", "1p", "--file", "b.dc", "-"])
+    );
+}
+
+#[test]
+fn test_parse_args_stdin_by_default() {
+    assert_eq!(
+        Ok(Invocation::Run(vec![ProgramSource::Stdin])),
+        test_parse_args(&[])
+    );
+}
+
+#[test]
+fn test_parse_args_attached_arguments() {
+    assert_eq!(
+        Ok(Invocation::Run(vec![
+            ProgramSource::Text("1p".to_string()),
+            ProgramSource::Text("2p".to_string()),
+            ProgramSource::File("c.dc".to_string()),
+        ])),
+        test_parse_args(&["-e1p", "--expression=2p", "-fc.dc"])
+    );
+}
+
+#[test]
+fn test_parse_args_file_stdin() {
+    assert_eq!(
+        Ok(Invocation::Run(vec![
+            ProgramSource::Stdin,
+            ProgramSource::Stdin,
+            ProgramSource::Stdin,
+        ])),
+        test_parse_args(&["-f", "-", "-f-", "--file=-"])
+    );
+}
+
+#[test]
+fn test_parse_args_end_of_options() {
+    assert_eq!(
+        Ok(Invocation::Run(vec![
+            ProgramSource::File("-e".to_string()),
+            ProgramSource::Stdin,
+        ])),
+        test_parse_args(&["--", "-e", "-"])
+    );
+}
+
+#[test]
+fn test_parse_args_help_and_version() {
+    assert_eq!(Ok(Invocation::Help), test_parse_args(&["-e", "1p", "-h"]));
+    assert_eq!(Ok(Invocation::Help), test_parse_args(&["--help"]));
+    assert_eq!(Ok(Invocation::Version), test_parse_args(&["-V"]));
+    assert_eq!(Ok(Invocation::Version), test_parse_args(&["--version"]));
+}
+
+#[test]
+fn test_parse_args_combined_flags() {
+    assert_eq!(Ok(Invocation::Version), test_parse_args(&["-Vh"]));
+    assert_eq!(Ok(Invocation::Help), test_parse_args(&["-hV"]));
+    assert_eq!(Ok(Invocation::Version), test_parse_args(&["-Ve1p"]));
+    assert_eq!(Err(ArgsError::InvalidOption('z')), test_parse_args(&["-zh"]));
+}
+
+#[test]
+fn test_parse_args_strict() {
+    let mut options = Options::default();
+    assert_eq!(
+        Ok(Invocation::<String, String>::Run(vec![ProgramSource::Text("1p".to_string())])),
+        parse_args(vec!["-e1p".to_string(), "--strict".to_string()].into_iter(), &mut options)
+    );
+    assert!(options.strict);
+}
+
+#[cfg(feature = "repl")]
+#[test]
+fn test_parse_args_prompt() {
+    let mut options = Options::default();
+    assert!(parse_args::<String, String, _, _>(
+        vec!["--prompt=top".to_string()].into_iter(),
+        &mut options
+    )
+    .is_ok());
+    assert_eq!(repl::Prompt::Top, options.prompt);
+    assert!(parse_args::<String, String, _, _>(
+        vec!["--prompt".to_string(), "depth".to_string()].into_iter(),
+        &mut options
+    )
+    .is_ok());
+    assert_eq!(repl::Prompt::Depth, options.prompt);
+    assert_eq!(
+        Err(ArgsError::InvalidArgument(
+            "--prompt".to_string(),
+            "fancy".to_string()
+        )),
+        test_parse_args(&["--prompt=fancy"])
+    );
+}
+
+#[cfg(not(feature = "repl"))]
+#[test]
+fn test_parse_args_prompt_unsupported() {
+    assert_eq!(
+        Err(ArgsError::Unsupported("--prompt".to_string())),
+        test_parse_args(&["--prompt=top"])
+    );
+}
+
+#[test]
+fn test_parse_args_errors() {
+    assert_eq!(Err(ArgsError::InvalidOption('z')), test_parse_args(&["-z"]));
+    assert_eq!(
+        Err(ArgsError::UnrecognizedOption("--zz".to_string())),
+        test_parse_args(&["--zz"])
+    );
+    assert_eq!(Err(ArgsError::MissingArgument('e')), test_parse_args(&["-e"]));
+    assert_eq!(
+        Err(ArgsError::MissingLongArgument("--file".to_string())),
+        test_parse_args(&["--file"])
+    );
+    assert_eq!(
+        "option requires an argument -- 'f'",
+        ArgsError::MissingArgument('f').to_string()
+    );
+}
+
+#[test]
+fn test_dc_runs_stdin() {
+    let (stdout, stderr) = dc(
+        Vec::<String>::new().into_iter(),
+        std::io::Cursor::new(b"1 2+p".to_vec()),
+        Vec::new(),
+        Vec::new(),
+    );
+    assert_eq!(b"3\n".to_vec(), stdout);
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn test_dc_runs_stdin_by_line() {
+    let (stdout, stderr) = dc(
+        vec!["-", "-e", "2p"].into_iter(),
+        std::io::Cursor::new(b"?p\n5\n".to_vec()),
+        Vec::new(),
+        Vec::new(),
+    );
+    assert_eq!(b"5\n2\n".to_vec(), stdout);
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn test_dc_help() {
+    let (stdout, _) = dc(
+        vec!["--help".to_string()].into_iter(),
+        std::io::empty(),
+        Vec::new(),
+        Vec::new(),
+    );
+    assert_eq!(USAGE.as_bytes().to_vec(), stdout);
+}
+
+#[cfg(test)]
+fn test_dc_with_options(args: &[&str], options: &Options) -> (String, String, i32) {
+    let (stdout, stderr, status) = dc_with_options(
+        args.iter().map(|arg| arg.to_string()),
+        std::io::empty(),
+        Vec::new(),
+        Vec::new(),
+        options,
+    );
+    (
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+        status,
+    )
+}
+
+#[test]
+fn test_exit_status_success() {
+    assert_eq!(
+        ("3\n".to_string(), String::new(), EXIT_SUCCESS),
+        test_dc_with_options(&["-e", "1 2+p"], &Options::default())
+    );
+}
+
+#[test]
+fn test_exit_status_execution_error() {
+    assert_eq!(
+        ("1\n".to_string(), "dc: stack empty\n".to_string(), EXIT_FAILURE),
+        test_dc_with_options(&["-e", "+ 1p"], &Options::default())
+    );
+}
+
+#[test]
+fn test_exit_status_missing_file() {
+    let (stdout, stderr, status) =
+        test_dc_with_options(&["-f", "/nonexistent/file.dc", "-e", "1p"], &Options::default());
+    assert_eq!("1\n", stdout);
+    assert!(stderr.starts_with("dc: "));
+    assert_eq!(EXIT_FAILURE, status);
+}
+
+#[test]
+fn test_exit_status_invalid_option() {
+    let (stdout, stderr, status) = test_dc_with_options(&["-z"], &Options::default());
+    assert_eq!("", stdout);
+    assert!(stderr.starts_with("dc: invalid option -- 'z'\nUsage: "));
+    assert_eq!(EXIT_FAILURE, status);
+}
+
+#[test]
+fn test_quit_stops_sources() {
+    assert_eq!(
+        (String::new(), String::new(), EXIT_SUCCESS),
+        test_dc_with_options(&["-e", "q", "-e", "1p"], &Options::default())
+    );
+    assert_eq!(
+        ("1\n".to_string(), String::new(), EXIT_SUCCESS),
+        test_dc_with_options(&["-e", "1p [q]x 2p", "-e", "3p"], &Options::default())
+    );
+}
+
+#[test]
+fn test_strict_stops_at_first_error() {
+    assert_eq!(
+        ("1\n".to_string(), "dc: stack empty\n".to_string(), EXIT_FAILURE),
+        test_dc_with_options(&["--strict", "-e", "1p + 2p", "-e", "3p"], &Options::default())
+    );
 }
 
-fn print_help(code: i32) {
-    std::process::exit(code);
+#[test]
+fn test_strict_unwinds_macros() {
+    let options = Options {
+        strict: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        (String::new(), "dc: stack empty\n".to_string(), EXIT_FAILURE),
+        test_dc_with_options(&["-e", "[[+ 1p]x 2p]x 3p"], &options)
+    );
 }
 
-fn print_version(code: i32) {
-    std::process::exit(code);
+#[test]
+fn test_strict_missing_file() {
+    let (stdout, _, status) = test_dc_with_options(
+        &["--strict", "-f", "/nonexistent/file.dc", "-e", "1p"],
+        &Options::default(),
+    );
+    assert_eq!("", stdout);
+    assert_eq!(EXIT_FAILURE, status);
 }
@@ -0,0 +1,525 @@
+use std::cmp;
+
+use bigdecimal::BigDecimal;
+use num;
+use num::bigint::BigInt;
+use num::{Integer, One, Signed, ToPrimitive, Zero};
+
+use dcstack::DCError;
+
+// 2^24 bits are about 5 million decimal digits: anything bigger than that
+// would keep the process busy for a very long time
+const MAX_POW_BITS: u64 = 1 << 24;
+
+fn ten_to_the(power: u64) -> BigInt {
+    num::pow(BigInt::from(10), power as usize)
+}
+
+// BigDecimal may have negative scales, dc numbers never do
+fn to_bigint_and_scale(n: &BigDecimal) -> (BigInt, u64) {
+    let (digits, scale) = n.as_bigint_and_exponent();
+    if scale < 0 {
+        (digits * ten_to_the(-scale as u64), 0)
+    } else {
+        (digits, scale as u64)
+    }
+}
+
+/// Raises `base` to the integer part of `exponent`.
+///
+/// The scale of the result is `min(a*b, max(k, a))` where `a` is the scale
+/// of the base, `b` the exponent and `k` the precision. Negative exponents
+/// give the reciprocal at scale `k`.
+pub fn pow(
+    base: &BigDecimal,
+    exponent: &BigDecimal,
+    precision: u64,
+) -> Result<BigDecimal, DCError> {
+    let exponent = exponent
+        .with_scale(0)
+        .as_bigint_and_exponent()
+        .0
+        .to_i64()
+        .ok_or(DCError::ExponentTooLarge)?;
+    if exponent == 0 {
+        return Ok(BigDecimal::from(1));
+    }
+
+    let (digits, scale) = to_bigint_and_scale(base);
+    let abs_exponent = exponent.unsigned_abs();
+    if digits.is_zero() {
+        return if exponent < 0 {
+            Err(DCError::DivideByZero)
+        } else {
+            Ok(BigDecimal::zero())
+        };
+    }
+
+    let power_scale = scale
+        .checked_mul(abs_exponent)
+        .ok_or(DCError::ExponentTooLarge)?;
+    // powers of +-1 are cheap, but their scale still needs to be handled
+    let digits_bits = if digits.abs() == BigInt::from(1) {
+        0
+    } else {
+        (digits.bits() as u64).saturating_mul(abs_exponent)
+    };
+    if digits_bits.saturating_add(power_scale.saturating_mul(4)) > MAX_POW_BITS {
+        return Err(DCError::ExponentTooLarge);
+    }
+    let power = num::pow(digits, abs_exponent as usize);
+
+    if exponent > 0 {
+        let result_scale = cmp::min(power_scale, cmp::max(precision, scale));
+        Ok(BigDecimal::new(power, power_scale as i64).with_scale(result_scale as i64))
+    } else {
+        let numerator = ten_to_the(power_scale + precision);
+        Ok(BigDecimal::new(numerator / power, precision as i64))
+    }
+}
+
+// the scale dc gives to the result of `+` and `-`
+fn additive_scale(lhs: &BigDecimal, rhs: &BigDecimal) -> u64 {
+    cmp::max(to_bigint_and_scale(lhs).1, to_bigint_and_scale(rhs).1)
+}
+
+/// `+`: the sum at the largest scale of the operands.
+pub fn add(lhs: &BigDecimal, rhs: &BigDecimal) -> BigDecimal {
+    (lhs + rhs).with_scale(additive_scale(lhs, rhs) as i64)
+}
+
+/// `-`: the difference at the largest scale of the operands.
+pub fn sub(lhs: &BigDecimal, rhs: &BigDecimal) -> BigDecimal {
+    (lhs - rhs).with_scale(additive_scale(lhs, rhs) as i64)
+}
+
+/// `*`: the product truncated to `min(a+b, max(k, a, b))` digits, where `a`
+/// and `b` are the scales of the operands and `k` the precision.
+pub fn mul(lhs: &BigDecimal, rhs: &BigDecimal, precision: u64) -> BigDecimal {
+    let (lhs, lhs_scale) = to_bigint_and_scale(lhs);
+    let (rhs, rhs_scale) = to_bigint_and_scale(rhs);
+    let product_scale = lhs_scale + rhs_scale;
+    let scale = cmp::min(
+        product_scale,
+        cmp::max(precision, cmp::max(lhs_scale, rhs_scale)),
+    );
+    BigDecimal::new(lhs * rhs, product_scale as i64).with_scale(scale as i64)
+}
+
+fn check_divisor(divisor: &BigDecimal, error: DCError) -> Result<(), DCError> {
+    if divisor.is_zero() {
+        return Err(error);
+    }
+    Ok(())
+}
+
+// a / b truncated to `precision` digits, the divisor must not be zero
+fn quotient(dividend: &BigDecimal, divisor: &BigDecimal, precision: u64) -> BigDecimal {
+    let (dividend, dividend_scale) = to_bigint_and_scale(dividend);
+    let (divisor, divisor_scale) = to_bigint_and_scale(divisor);
+    let numerator = dividend * ten_to_the(divisor_scale + precision);
+    let denominator = divisor * ten_to_the(dividend_scale);
+    BigDecimal::new(numerator / denominator, precision as i64)
+}
+
+// a - (a / b) * b, with a scale of max(scale(a), scale(b) + k)
+fn remainder(
+    dividend: &BigDecimal,
+    divisor: &BigDecimal,
+    quotient: &BigDecimal,
+    precision: u64,
+) -> BigDecimal {
+    let scale = cmp::max(
+        to_bigint_and_scale(dividend).1,
+        to_bigint_and_scale(divisor).1 + precision,
+    );
+    (dividend - &(quotient * divisor)).with_scale(scale as i64)
+}
+
+/// `/`: the quotient truncated to `precision` digits.
+pub fn div(
+    dividend: &BigDecimal,
+    divisor: &BigDecimal,
+    precision: u64,
+) -> Result<BigDecimal, DCError> {
+    check_divisor(divisor, DCError::DivideByZero)?;
+    Ok(quotient(dividend, divisor, precision))
+}
+
+/// `%`: the remainder of the division truncated to `precision` digits.
+pub fn rem(
+    dividend: &BigDecimal,
+    divisor: &BigDecimal,
+    precision: u64,
+) -> Result<BigDecimal, DCError> {
+    check_divisor(divisor, DCError::RemainderByZero)?;
+    let quotient = quotient(dividend, divisor, precision);
+    Ok(remainder(dividend, divisor, &quotient, precision))
+}
+
+/// `~`: both the quotient and the remainder, as `/` and `%` would compute them.
+pub fn divmod(
+    dividend: &BigDecimal,
+    divisor: &BigDecimal,
+    precision: u64,
+) -> Result<(BigDecimal, BigDecimal), DCError> {
+    check_divisor(divisor, DCError::DivideByZero)?;
+    let quotient = quotient(dividend, divisor, precision);
+    let remainder = remainder(dividend, divisor, &quotient, precision);
+    Ok((quotient, remainder))
+}
+
+// floor(sqrt(n)) with Newton's method, starting from above the root
+fn isqrt(n: &BigInt) -> BigInt {
+    if *n < BigInt::from(2) {
+        return n.clone();
+    }
+    let mut root = BigInt::one() << n.bits().div_ceil(2);
+    loop {
+        let next = (&root + n / &root) >> 1;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+/// `v`: the square root truncated to `max(precision, scale(n))` digits.
+pub fn sqrt(n: &BigDecimal, precision: u64) -> Result<BigDecimal, DCError> {
+    if n.is_negative() {
+        return Err(DCError::SqrtOfNegative);
+    }
+    let (digits, scale) = to_bigint_and_scale(n);
+    let result_scale = cmp::max(precision, scale);
+    let root = isqrt(&(digits * ten_to_the(2 * result_scale - scale)));
+    Ok(BigDecimal::new(root, result_scale as i64))
+}
+
+/// `P`: the integer part of `n` as a big endian base 256 number, the sign is
+/// ignored and zero has no bytes at all.
+pub fn to_base256_bytes(n: &BigDecimal) -> Vec<u8> {
+    let (digits, _scale) = n.with_scale(0).into_bigint_and_exponent();
+    if digits.is_zero() {
+        return Vec::new();
+    }
+    digits.to_bytes_be().1
+}
+
+/// `a`: the low order byte of the integer part of `n`.
+pub fn low_order_byte(n: &BigDecimal) -> u8 {
+    let (digits, _scale) = n.with_scale(0).into_bigint_and_exponent();
+    digits
+        .mod_floor(&BigInt::from(256))
+        .to_u8()
+        .expect("a number modulo 256 fits in a byte")
+}
+
+/// `Z`: the number of significant decimal digits, counting the fractional
+/// ones but not the leading zeros (so `.001` has 1 digit, and so does 0).
+pub fn digits(n: &BigDecimal) -> u64 {
+    let (digits, scale) = n.as_bigint_and_exponent();
+    if digits.is_zero() {
+        return 1;
+    }
+    // with b bits, |digits| >= 2^(b-1), so it has at least (b-1)*log10(2)
+    // decimal digits, and at most one more; the estimate is lowered so that
+    // rounding never makes it too large, and the comparison adds the digit
+    // it may miss (cheaper than converting a huge number to decimal)
+    let bits = digits.bits() as f64;
+    let estimate = ((bits - 1.0) * ::std::f64::consts::LOG10_2 - 0.5).floor() as i64 + 1;
+    let estimate = cmp::max(estimate, 0) as u64;
+    let significant = if digits.abs() >= ten_to_the(estimate) {
+        estimate + 1
+    } else {
+        estimate
+    };
+    if scale < 0 {
+        significant + (-scale) as u64
+    } else {
+        significant
+    }
+}
+
+/// `X`: the number of fractional decimal digits.
+pub fn fraction_digits(n: &BigDecimal) -> u64 {
+    cmp::max(n.as_bigint_and_exponent().1, 0) as u64
+}
+
+/// The integer part of `n` as dc converts the operands of `i`, `o` and `k`:
+/// `None` when it does not fit or when a nonzero number truncates to zero,
+/// the cases where dc warns and punts.
+pub fn to_simple_integer(n: &BigDecimal) -> Option<i64> {
+    let integer = n.with_scale(0).as_bigint_and_exponent().0;
+    if integer.is_zero() && !n.is_zero() {
+        return None;
+    }
+    integer.to_i64()
+}
+
+fn integer_operand(n: &BigDecimal, error: DCError) -> Result<BigInt, DCError> {
+    let (digits, scale) = n.as_bigint_and_exponent();
+    if scale != 0 {
+        return Err(error);
+    }
+    Ok(digits)
+}
+
+/// Computes `base^exponent % modulus` with square and multiply, never
+/// materializing `base^exponent`. Like `%`, the sign of the result follows
+/// the sign of `base^exponent`.
+pub fn modexp(
+    base: &BigDecimal,
+    exponent: &BigDecimal,
+    modulus: &BigDecimal,
+) -> Result<BigDecimal, DCError> {
+    let base = integer_operand(base, DCError::NonZeroScaleInBase)?;
+    let mut exponent = integer_operand(exponent, DCError::NonZeroScaleInExponent)?;
+    let modulus = integer_operand(modulus, DCError::NonZeroScaleInModulus)?;
+    if modulus.is_zero() {
+        return Err(DCError::RemainderByZero);
+    }
+    if exponent.is_negative() {
+        return Err(DCError::NegativeExponent);
+    }
+
+    let mut result = BigInt::one() % &modulus;
+    let mut square = base % &modulus;
+    while !exponent.is_zero() {
+        if exponent.is_odd() {
+            result = (result * &square) % &modulus;
+        }
+        square = (&square * &square) % &modulus;
+        exponent = exponent >> 1;
+    }
+    Ok(BigDecimal::new(result, 0))
+}
+
+#[cfg(test)]
+fn decimal(s: &str) -> BigDecimal {
+    use std::str::FromStr;
+    BigDecimal::from_str(s).expect("valid decimal")
+}
+
+#[test]
+fn test_pow_integer() {
+    assert_eq!(Ok(decimal("1024")), pow(&decimal("2"), &decimal("10"), 0));
+    assert_eq!(Ok(decimal("-27")), pow(&decimal("-3"), &decimal("3"), 0));
+    assert_eq!(Ok(decimal("1")), pow(&decimal("0"), &decimal("0"), 0));
+}
+
+#[test]
+fn test_pow_scale() {
+    // min(a*b, max(k, a))
+    let result = pow(&decimal("1.5"), &decimal("3"), 0).unwrap();
+    assert_eq!((BigInt::from(33), 1), result.as_bigint_and_exponent());
+    let result = pow(&decimal("1.5"), &decimal("3"), 10).unwrap();
+    assert_eq!((BigInt::from(3375), 3), result.as_bigint_and_exponent());
+}
+
+#[test]
+fn test_pow_negative_exponent() {
+    let result = pow(&decimal("2"), &decimal("-2"), 3).unwrap();
+    assert_eq!((BigInt::from(250), 3), result.as_bigint_and_exponent());
+    let result = pow(&decimal("3"), &decimal("-1"), 0).unwrap();
+    assert_eq!((BigInt::from(0), 0), result.as_bigint_and_exponent());
+    assert_eq!(Err(DCError::DivideByZero), pow(&decimal("0"), &decimal("-1"), 0));
+}
+
+#[test]
+fn test_pow_too_large() {
+    assert_eq!(
+        Err(DCError::ExponentTooLarge),
+        pow(&decimal("2"), &decimal("100000000000000000000"), 0)
+    );
+    assert_eq!(
+        Err(DCError::ExponentTooLarge),
+        pow(&decimal("2"), &decimal("1000000000"), 0)
+    );
+    assert_eq!(
+        Err(DCError::ExponentTooLarge),
+        pow(&decimal(".00000001"), &decimal("1000000000"), 0)
+    );
+    assert_eq!(
+        Ok(decimal("-1")),
+        pow(&decimal("-1"), &decimal("1000000000001"), 0)
+    );
+}
+
+#[test]
+fn test_add_sub() {
+    assert_eq!(
+        (BigInt::from(150), 2),
+        add(&decimal("1"), &decimal("0.50")).as_bigint_and_exponent()
+    );
+    assert_eq!(
+        (BigInt::from(0), 3),
+        sub(&decimal("1.5"), &decimal("1.500")).as_bigint_and_exponent()
+    );
+    assert_eq!(
+        (BigInt::from(-5), 1),
+        sub(&decimal("1"), &decimal("1.5")).as_bigint_and_exponent()
+    );
+}
+
+#[test]
+fn test_mul() {
+    // min(a + b, max(k, a, b))
+    assert_eq!(
+        (BigInt::from(2), 1),
+        mul(&decimal("0.5"), &decimal("0.5"), 0).as_bigint_and_exponent()
+    );
+    assert_eq!(
+        (BigInt::from(25), 2),
+        mul(&decimal("0.5"), &decimal("0.5"), 5).as_bigint_and_exponent()
+    );
+    assert_eq!(
+        (BigInt::from(-4111), 3),
+        mul(&decimal("-1.234"), &decimal("3.332"), 0).as_bigint_and_exponent()
+    );
+    assert_eq!(
+        (BigInt::from(6), 0),
+        mul(&decimal("2"), &decimal("3"), 10).as_bigint_and_exponent()
+    );
+}
+
+#[test]
+fn test_div() {
+    let result = div(&decimal("10"), &decimal("3"), 2).unwrap();
+    assert_eq!((BigInt::from(333), 2), result.as_bigint_and_exponent());
+    let result = div(&decimal("-7"), &decimal("2"), 0).unwrap();
+    assert_eq!((BigInt::from(-3), 0), result.as_bigint_and_exponent());
+    let result = div(&decimal("1"), &decimal("7"), 150).unwrap();
+    assert_eq!(150, result.as_bigint_and_exponent().1);
+    assert_eq!(Err(DCError::DivideByZero), div(&decimal("1"), &decimal("0.0"), 2));
+}
+
+#[test]
+fn test_rem() {
+    assert_eq!(Ok(decimal("1")), rem(&decimal("10"), &decimal("3"), 0));
+    assert_eq!(Ok(decimal("-1")), rem(&decimal("-7"), &decimal("2"), 0));
+    // 10 - 3.33 * 3
+    let result = rem(&decimal("10"), &decimal("3"), 2).unwrap();
+    assert_eq!((BigInt::from(1), 2), result.as_bigint_and_exponent());
+    assert_eq!(Err(DCError::RemainderByZero), rem(&decimal("1"), &decimal("0"), 0));
+}
+
+#[test]
+fn test_divmod() {
+    assert_eq!(
+        Ok((decimal("3"), decimal("1"))),
+        divmod(&decimal("10"), &decimal("3"), 0)
+    );
+    assert_eq!(
+        Err(DCError::DivideByZero),
+        divmod(&decimal("10"), &decimal("0"), 0)
+    );
+}
+
+#[test]
+fn test_isqrt() {
+    for n in 0..1000u32 {
+        let root = isqrt(&BigInt::from(n));
+        assert!(&root * &root <= BigInt::from(n));
+        assert!((&root + 1) * (&root + 1) > BigInt::from(n));
+    }
+}
+
+#[test]
+fn test_sqrt() {
+    let result = sqrt(&decimal("2"), 3).unwrap();
+    assert_eq!((BigInt::from(1414), 3), result.as_bigint_and_exponent());
+    let result = sqrt(&decimal(".04"), 0).unwrap();
+    assert_eq!((BigInt::from(20), 2), result.as_bigint_and_exponent());
+    let result = sqrt(&decimal("1e400"), 0).unwrap();
+    assert_eq!(ten_to_the(200), result.as_bigint_and_exponent().0);
+    assert_eq!(Err(DCError::SqrtOfNegative), sqrt(&decimal("-1"), 0));
+}
+
+#[test]
+fn test_to_base256_bytes() {
+    assert_eq!(vec![b'A'], to_base256_bytes(&decimal("65.9")));
+    assert_eq!(vec![1, 0], to_base256_bytes(&decimal("-256")));
+    assert_eq!(vec![0xff, 0xfe], to_base256_bytes(&decimal("65534")));
+    assert!(to_base256_bytes(&decimal("0.5")).is_empty());
+}
+
+#[test]
+fn test_low_order_byte() {
+    assert_eq!(b'A', low_order_byte(&decimal("65.9")));
+    assert_eq!(b'A', low_order_byte(&decimal("321")));
+    assert_eq!(255, low_order_byte(&decimal("-1")));
+    assert_eq!(0, low_order_byte(&decimal("0")));
+}
+
+#[test]
+fn test_digits() {
+    assert_eq!(1, digits(&decimal(".001")));
+    assert_eq!(1, digits(&decimal("0")));
+    assert_eq!(1, digits(&decimal("0.000")));
+    assert_eq!(3, digits(&decimal("100")));
+    assert_eq!(3, digits(&decimal("-1.50")));
+    assert_eq!(5, digits(&decimal("12.345")));
+    assert_eq!(3, digits(&decimal("1e2")));
+    assert_eq!(20, digits(&decimal("99999999999999999999")));
+    assert_eq!(21, digits(&decimal("100000000000000000000")));
+    assert_eq!(1, digits(&decimal("9")));
+    assert_eq!(2, digits(&decimal("-10")));
+    for power in 0..200 {
+        assert_eq!(power + 1, digits(&BigDecimal::new(ten_to_the(power), 0)));
+        assert_eq!(power + 1, digits(&BigDecimal::new(ten_to_the(power + 1) - 1, 0)));
+    }
+}
+
+#[test]
+fn test_fraction_digits() {
+    assert_eq!(3, fraction_digits(&decimal(".001")));
+    assert_eq!(0, fraction_digits(&decimal("100")));
+    assert_eq!(2, fraction_digits(&decimal("-1.50")));
+    assert_eq!(0, fraction_digits(&decimal("1e2")));
+}
+
+#[test]
+fn test_to_simple_integer() {
+    assert_eq!(Some(0), to_simple_integer(&decimal("0.00")));
+    assert_eq!(Some(10), to_simple_integer(&decimal("10.9")));
+    assert_eq!(Some(-2), to_simple_integer(&decimal("-2.5")));
+    assert_eq!(None, to_simple_integer(&decimal("0.5")));
+    assert_eq!(None, to_simple_integer(&decimal("-0.5")));
+    assert_eq!(None, to_simple_integer(&decimal("100000000000000000000")));
+}
+
+#[test]
+fn test_modexp() {
+    assert_eq!(
+        Ok(decimal("445")),
+        modexp(&decimal("4"), &decimal("13"), &decimal("497"))
+    );
+    assert_eq!(
+        Ok(decimal("0")),
+        modexp(&decimal("4"), &decimal("0"), &decimal("1"))
+    );
+    assert_eq!(
+        Ok(decimal("-4")),
+        modexp(&decimal("-4"), &decimal("3"), &decimal("-5"))
+    );
+}
+
+#[test]
+fn test_modexp_errors() {
+    assert_eq!(
+        Err(DCError::RemainderByZero),
+        modexp(&decimal("4"), &decimal("13"), &decimal("0"))
+    );
+    assert_eq!(
+        Err(DCError::NegativeExponent),
+        modexp(&decimal("4"), &decimal("-1"), &decimal("5"))
+    );
+    assert_eq!(
+        Err(DCError::NonZeroScaleInExponent),
+        modexp(&decimal("4"), &decimal("1.0"), &decimal("5"))
+    );
+    assert_eq!(
+        Err(DCError::NonZeroScaleInModulus),
+        modexp(&decimal("4"), &decimal("1"), &decimal("5.5"))
+    );
+}
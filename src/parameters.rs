@@ -0,0 +1,136 @@
+use std::error;
+use std::fmt;
+
+// dc only reads digits up to F
+const MAX_INPUT_RADIX: i64 = 16;
+
+static INVALID_INPUT_RADIX: &str = "input base must be a number between 2 and 16 (inclusive)";
+static INVALID_OUTPUT_RADIX: &str = "output base must be a number greater than 1";
+static INVALID_PRECISION: &str = "scale must be a nonnegative number";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParameterError {
+    InputRadix,
+    OutputRadix,
+    Precision,
+}
+
+impl ParameterError {
+    pub fn message(&self) -> &str {
+        match *self {
+            ParameterError::InputRadix => INVALID_INPUT_RADIX,
+            ParameterError::OutputRadix => INVALID_OUTPUT_RADIX,
+            ParameterError::Precision => INVALID_PRECISION,
+        }
+    }
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.message())
+    }
+}
+
+impl error::Error for ParameterError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+/// The values set by `i`, `o` and `k`: the setters refuse anything dc would
+/// refuse, so they are always valid.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parameters {
+    input_radix: u32,  // [2,16]
+    output_radix: u32, // >= 2
+    precision: u64,    // always in decimal
+}
+
+impl Default for Parameters {
+    fn default() -> Parameters {
+        Parameters {
+            input_radix: 10,
+            output_radix: 10,
+            precision: 0,
+        }
+    }
+}
+
+impl Parameters {
+    pub fn input_radix(&self) -> u32 {
+        self.input_radix
+    }
+
+    pub fn output_radix(&self) -> u32 {
+        self.output_radix
+    }
+
+    pub fn precision(&self) -> u64 {
+        self.precision
+    }
+
+    pub fn set_input_radix(&mut self, radix: i64) -> Result<(), ParameterError> {
+        if !(2..=MAX_INPUT_RADIX).contains(&radix) {
+            return Err(ParameterError::InputRadix);
+        }
+        self.input_radix = radix as u32;
+        Ok(())
+    }
+
+    /// Radixes that do not fit in 32 bits are refused as well.
+    pub fn set_output_radix(&mut self, radix: i64) -> Result<(), ParameterError> {
+        if !(2..=i64::from(u32::MAX)).contains(&radix) {
+            return Err(ParameterError::OutputRadix);
+        }
+        self.output_radix = radix as u32;
+        Ok(())
+    }
+
+    pub fn set_precision(&mut self, precision: i64) -> Result<(), ParameterError> {
+        if precision < 0 {
+            return Err(ParameterError::Precision);
+        }
+        self.precision = precision as u64;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_defaults() {
+    let parameters = Parameters::default();
+    assert_eq!(10, parameters.input_radix());
+    assert_eq!(10, parameters.output_radix());
+    assert_eq!(0, parameters.precision());
+}
+
+#[test]
+fn test_input_radix_bounds() {
+    let mut parameters = Parameters::default();
+    assert_eq!(Ok(()), parameters.set_input_radix(2));
+    assert_eq!(Ok(()), parameters.set_input_radix(16));
+    assert_eq!(16, parameters.input_radix());
+    assert_eq!(Err(ParameterError::InputRadix), parameters.set_input_radix(1));
+    assert_eq!(Err(ParameterError::InputRadix), parameters.set_input_radix(17));
+    assert_eq!(Err(ParameterError::InputRadix), parameters.set_input_radix(-10));
+    assert_eq!(16, parameters.input_radix());
+}
+
+#[test]
+fn test_output_radix_bounds() {
+    let mut parameters = Parameters::default();
+    assert_eq!(Ok(()), parameters.set_output_radix(2));
+    assert_eq!(Ok(()), parameters.set_output_radix(1000));
+    assert_eq!(Err(ParameterError::OutputRadix), parameters.set_output_radix(1));
+    assert_eq!(Err(ParameterError::OutputRadix), parameters.set_output_radix(-2));
+    assert_eq!(Err(ParameterError::OutputRadix), parameters.set_output_radix(1 << 40));
+    assert_eq!(1000, parameters.output_radix());
+}
+
+#[test]
+fn test_precision_bounds() {
+    let mut parameters = Parameters::default();
+    assert_eq!(Ok(()), parameters.set_precision(1));
+    assert_eq!(Ok(()), parameters.set_precision(0));
+    assert_eq!(Err(ParameterError::Precision), parameters.set_precision(-1));
+    assert_eq!(0, parameters.precision());
+}
@@ -4,6 +4,7 @@ use std::error;
 
 use instructions::*;
 
+const STRING_START: u8 = b'[';
 const STRING_TERMINATOR: u8 = b']';
 const NEWLINE_BYTE: u8 = b'\n';
 
@@ -38,8 +39,8 @@ pub struct ParserError<'a> {
     pub unparsed: &'a [u8],
 }
 
-static EOP_MESSAGE: &'static str = "end of stream";
-static INVALID_CHARACTER: &'static str = "invalid character";
+static EOP_MESSAGE: &str = "end of stream";
+static INVALID_CHARACTER: &str = "invalid character";
 
 impl<'a> fmt::Display for ParserError<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -58,8 +59,8 @@ impl<'a> fmt::Display for ParserError<'a> {
 impl<'a> error::Error for ParserError<'a> {
     fn description(&self) -> &str {
         match &self.error_type {
-            &ParserErrorType::EOP(..) => &EOP_MESSAGE,
-            &ParserErrorType::InvalidCharacter(..) => &INVALID_CHARACTER,
+            &ParserErrorType::EOP(..) => EOP_MESSAGE,
+            &ParserErrorType::InvalidCharacter(..) => INVALID_CHARACTER,
         }
     }
 }
@@ -69,6 +70,7 @@ enum ParserState {
     TopLevel,
     Error(usize, ParserErrorType),
     Num {
+        sign: Sign,
         start: usize,
         end: usize,
         dot_position: Option<usize>,
@@ -79,6 +81,8 @@ enum ParserState {
     ReadUntilByte {
         terminator: Terminator,
         range: Range<usize>,
+        // brackets opened inside a string and not closed yet
+        depth: usize,
     },
     Register(RegisterOperationType),
     Mark,
@@ -127,14 +131,82 @@ macro_rules! push_and_toplevel {
 // dc: ']' (0135) unimplemented
 // 20
 // 10
+#[cfg(test)]
 pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
+    parse_instructions(program_text, false, &mut 0).map(|(program, _)| program)
+}
+
+/// Parses the first instruction of `program_text`, returning it with the
+/// number of bytes it spans, the blanks around it included. There is no
+/// instruction when only blanks are left.
+pub fn parse_instruction(
+    program_text: &[u8],
+) -> Result<Option<(Instruction<'_>, usize)>, ParserError<'_>> {
+    let (program, length) = parse_instructions(program_text, true, &mut 0)?;
+    Ok(program.instructions.first().map(|&instruction| (instruction, length)))
+}
+
+/// The brackets of a string still open at the end of `program_text`, when
+/// it starts `open` brackets deep in a string: while some are open, the text
+/// that follows is part of the string.
+pub fn open_brackets(program_text: &[u8], mut open: usize) -> usize {
+    let mut position = 0;
+    while open > 0 {
+        match program_text.get(position) {
+            Some(&STRING_START) => open += 1,
+            Some(&STRING_TERMINATOR) => open -= 1,
+            Some(_) => {}
+            None => return open,
+        }
+        position += 1;
+    }
+    let mut text = &program_text[position..];
+    loop {
+        match parse_instructions(text, false, &mut open) {
+            // the execution goes on after the characters it cannot parse
+            Err(ref parse_error) if !parse_error.unparsed.is_empty() => {
+                text = parse_error.unparsed
+            }
+            _ => return open,
+        }
+    }
+}
+
+// parses the whole text, or up to the end of its first instruction, returning
+// where parsing stopped; open is set to the brackets of a string the text
+// ends in
+fn parse_instructions<'a>(
+    program_text: &'a [u8],
+    first_only: bool,
+    open: &mut usize,
+) -> Result<(Program<'a>, usize), ParserError<'a>> {
     let mut state = ParserState::TopLevel;
     let mut program = Program::default();
     let mut position: usize = 0;
 
     loop {
+        // the blanks after the instruction are skipped too, so that the caller
+        // knows whether it is the last one
+        if first_only
+            && state == ParserState::TopLevel
+            && !program.instructions.is_empty()
+            && !matches!(program_text.get(position), Some(b' ') | Some(b'\n'))
+        {
+            return Ok((program, position));
+        }
         if position >= program_text.len() {
-            return match state {
+            *open = match state {
+                ParserState::PrepareToReadUntil {
+                    terminator: Terminator::String,
+                } => 1,
+                ParserState::ReadUntilByte {
+                    terminator: Terminator::String,
+                    depth,
+                    ..
+                } => depth + 1,
+                _ => 0,
+            };
+            let result = match state {
                 ParserState::Error(position, error_type) => Err(ParserError {
                     position,
                     error_type,
@@ -143,12 +215,14 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                 }),
                 ParserState::TopLevel => Ok(program),
                 ParserState::Num {
+                    sign,
                     start,
                     end,
                     dot_position,
                 } => {
                     let pos = dot_position.unwrap_or(end);
                    program.push(Instruction::Num(
+                        sign,
                         &program_text[start..pos],
                         &program_text[::std::cmp::min(pos + 1, end)..end],
                     ));
@@ -158,7 +232,7 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                     position,
                     error_type: ParserErrorType::EOP("was expecting a register".to_string()),
                     program,
-                    unparsed: &program_text[position + 1..],
+                    unparsed: &program_text[program_text.len()..],
                 }),
                 ParserState::Mark => Ok(program),
                 // dc actually seg faults in this case
@@ -168,12 +242,13 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                     position,
                     error_type: ParserErrorType::EOP("string not completed".to_string()),
                     program,
-                    unparsed: &program_text[position + 1..],
+                    unparsed: &program_text[program_text.len()..],
                 }),
                 ParserState::PrepareToReadUntil { .. } => Ok(program),
                 ParserState::ReadUntilByte {
                     terminator: Terminator::String,
                     range,
+                    ..
                 } => {
                     program.push(Instruction::Str(&program_text[range]));
                     Ok(program)
@@ -181,6 +256,7 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                 ParserState::ReadUntilByte {
                     terminator: Terminator::System,
                     range,
+                    ..
                 } => {
                     program.push(Instruction::System(&program_text[range]));
                     Ok(program)
@@ -188,11 +264,13 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                 ParserState::ReadUntilByte {
                     terminator: Terminator::Comment,
                     range,
+                    ..
                 } => {
                     program.push(Instruction::Comment(&program_text[range]));
                     Ok(program)
                 }
             };
+            return result.map(|program| (program, program_text.len()));
         }
         state = match (state, program_text[position]) {
             (ParserState::Error(position, error_type), _) => {
@@ -206,17 +284,26 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
             (ParserState::TopLevel, 0) => {
                 incrementing![position; push_and_toplevel![program; Instruction::Nop]]
             }
+            (ParserState::TopLevel, b'_') => incrementing![position; ParserState::Num {
+                    sign: Sign::Negative,
+                    start: position + 1,
+                    end: position + 1,
+                    dot_position: None,
+            }],
             (ParserState::TopLevel, b'.') => incrementing![position; ParserState::Num {
+                    sign: Sign::Positive,
                     start: position,
                     end: position + 1,
                     dot_position: Some(position),
             }],
             (ParserState::TopLevel, b'0'...b'9') => incrementing![position; ParserState::Num {
+                    sign: Sign::Positive,
                     start: position,
                     end: position + 1,
                     dot_position: None,
             }],
             (ParserState::TopLevel, b'A'...b'F') => incrementing![position; ParserState::Num {
+                    sign: Sign::Positive,
                     start: position,
                     end: position + 1,
                     dot_position: None,
@@ -353,36 +440,40 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
 
             (
                 ParserState::Num {
+                    sign,
                     start,
                     end,
                     dot_position: None,
                 },
                 b'.',
             ) => {
-                incrementing![position; ParserState::Num{start, end: end + 1, dot_position: Some(position) }]
+                incrementing![position; ParserState::Num{sign, start, end: end + 1, dot_position: Some(position) }]
             }
             (
                 ParserState::Num {
+                    sign,
                     start,
                     end,
                     dot_position,
                 },
                 b'0'...b'9',
             ) => {
-                incrementing![position; ParserState::Num{start, end: end + 1, dot_position: dot_position }]
+                incrementing![position; ParserState::Num{sign, start, end: end + 1, dot_position: dot_position }]
             }
             (
                 ParserState::Num {
+                    sign,
                     start,
                     end,
                     dot_position,
                 },
                 b'A'...b'F',
             ) => {
-                incrementing![position; ParserState::Num{start, end: end + 1, dot_position: dot_position }]
+                incrementing![position; ParserState::Num{sign, start, end: end + 1, dot_position: dot_position }]
             }
             (
                 ParserState::Num {
+                    sign,
                     start,
                     end,
                     dot_position,
@@ -390,42 +481,69 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                 _,
             ) => {
                 let dot_pos = dot_position.unwrap_or(end);
-                push_and_toplevel![program; Instruction::Num(&program_text[start..dot_pos], &program_text[::std::cmp::min(dot_pos+1, end)..end])]
+                push_and_toplevel![program; Instruction::Num(sign, &program_text[start..dot_pos], &program_text[::std::cmp::min(dot_pos+1, end)..end])]
             }
             (ParserState::Register(register_operation_type), ch) => incrementing![
                 position; 
                 push_and_toplevel![ program; Instruction::RegisterOperation(register_operation_type, ch)]],
+            // !> is "not greater", i.e., less or equal
             (ParserState::Mark, b'>') => {
-                incrementing![position; ParserState::Register(RegisterOperationType::TosGeExecute)]
+                incrementing![position; ParserState::Register(RegisterOperationType::TosLeExecute)]
             }
             (ParserState::Mark, b'<') => {
-                incrementing![position; ParserState::Register(RegisterOperationType::TosLeExecute)]
+                incrementing![position; ParserState::Register(RegisterOperationType::TosGeExecute)]
             }
             (ParserState::Mark, b'=') => {
                 incrementing![position; ParserState::Register(RegisterOperationType::TosNeExecute)]
             }
             (ParserState::Mark, _) => {
-                incrementing![position; ParserState::ReadUntilByte { terminator: Terminator::System, range: position .. position+1 }]
+                incrementing![position; ParserState::ReadUntilByte { terminator: Terminator::System, range: position .. position+1, depth: 0 }]
             }
             (ParserState::PrepareToReadUntil { ref terminator }, ch) if *terminator == ch => {
                 incrementing![position; push_and_toplevel![program; Instruction::Str(&program_text[position..position])]]
             }
+            (ParserState::PrepareToReadUntil { terminator: Terminator::String }, STRING_START) => {
+                incrementing![position; ParserState::ReadUntilByte{terminator: Terminator::String, range: position..position+1, depth: 1}]
+            }
             (ParserState::PrepareToReadUntil { terminator }, _) => {
-                incrementing![position; ParserState::ReadUntilByte{terminator, range: position..position+1}]
+                incrementing![position; ParserState::ReadUntilByte{terminator, range: position..position+1, depth: 0}]
             }
             (
                 ParserState::ReadUntilByte {
                     terminator: Terminator::System,
                     range: Range { start, end },
+                    ..
                 },
                 NEWLINE_BYTE,
             ) => incrementing![
                 position;
                 push_and_toplevel![program; Instruction::System(&program_text[start .. end])]],
+            // like dc, strings can contain balanced brackets
+            (
+                ParserState::ReadUntilByte {
+                    terminator: Terminator::String,
+                    range: Range { start, end },
+                    depth,
+                },
+                STRING_START,
+            ) => {
+                incrementing![position; ParserState::ReadUntilByte{terminator: Terminator::String, range: start .. end+1, depth: depth + 1}]
+            }
+            (
+                ParserState::ReadUntilByte {
+                    terminator: Terminator::String,
+                    range: Range { start, end },
+                    depth,
+                },
+                STRING_TERMINATOR,
+            ) if depth > 0 => {
+                incrementing![position; ParserState::ReadUntilByte{terminator: Terminator::String, range: start .. end+1, depth: depth - 1}]
+            }
             (
                 ParserState::ReadUntilByte {
                     terminator: Terminator::String,
                     range: Range { start, end },
+                    ..
                 },
                 STRING_TERMINATOR,
             ) => incrementing![
@@ -435,6 +553,7 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                 ParserState::ReadUntilByte {
                     terminator: Terminator::Comment,
                     range: Range { start, end },
+                    ..
                 },
                 NEWLINE_BYTE,
             ) => incrementing![
@@ -444,10 +563,11 @@ pub fn parse(program_text: &[u8]) -> Result<Program, ParserError> {
                 ParserState::ReadUntilByte {
                     terminator,
                     range: Range { start, end },
+                    depth,
                 },
                 _,
             ) => {
-                incrementing![position; ParserState::ReadUntilByte{terminator, range: start .. end+1}]
+                incrementing![position; ParserState::ReadUntilByte{terminator, range: start .. end+1, depth}]
             }
         }
     }
@@ -509,22 +629,27 @@ parse_tests! {
     parse_test_i2: ("I", Ok(vec![Instruction::GetInputRadix])),
     parse_test_o2: ("O", Ok(vec![Instruction::GetOutputRadix])),
     parse_test_k2: ("K", Ok(vec![Instruction::GetPrecision])),
-    parse_test_0: ("0", Ok(vec![Instruction::Num("0".as_bytes(), "".as_bytes())])),
-    parse_test_0dot: ("0.", Ok(vec![Instruction::Num("0".as_bytes(), "".as_bytes())])),
-    parse_test_dot0: (".0", Ok(vec![Instruction::Num("".as_bytes(), "0".as_bytes())])),
-    parse_test_132763: ("132763", Ok(vec![Instruction::Num("132763".as_bytes(), "".as_bytes())])),
-    parse_test_1: ("1", Ok(vec![Instruction::Num("1".as_bytes(), "".as_bytes())])),
-    parse_test_1dot: ("1.", Ok(vec![Instruction::Num("1".as_bytes(), "".as_bytes())])),
-    parse_test_dot1: (".1", Ok(vec![Instruction::Num("".as_bytes(), "1".as_bytes())])),
-    parse_test_dot: (".", Ok(vec![Instruction::Num("".as_bytes(), "".as_bytes())])),
-    parse_test_dotdot: ("..", Ok(vec![Instruction::Num("".as_bytes(), "".as_bytes()), Instruction::Num("".as_bytes(), "".as_bytes())])),
-    parse_test_dot_dot: (". .", Ok(vec![Instruction::Num("".as_bytes(), "".as_bytes()), Instruction::Num("".as_bytes(), "".as_bytes())])),
-    parse_test_zero_dot_zero: ("0.0", Ok(vec![Instruction::Num("0".as_bytes(), "0".as_bytes())])),
-    parse_test_00: ("00", Ok(vec![Instruction::Num("00".as_bytes(), "".as_bytes())])),
-    parse_test_11: ("11", Ok(vec![Instruction::Num("11".as_bytes(), "".as_bytes())])),
-    parse_test_a2_dot_1: ("A.1", Ok(vec![Instruction::Num("A".as_bytes(), "1".as_bytes())])),
-    parse_test_0_0: ("0 0", Ok(vec![Instruction::Num("0".as_bytes(), "".as_bytes()), Instruction::Num("0".as_bytes(), "".as_bytes())])),
-    parse_test_1_1: ("1 1", Ok(vec![Instruction::Num("1".as_bytes(), "".as_bytes()), Instruction::Num("1".as_bytes(), "".as_bytes())])),
+    parse_test_0: ("0", Ok(vec![Instruction::Num(Sign::Positive, "0".as_bytes(), "".as_bytes())])),
+    parse_test_0dot: ("0.", Ok(vec![Instruction::Num(Sign::Positive, "0".as_bytes(), "".as_bytes())])),
+    parse_test_dot0: (".0", Ok(vec![Instruction::Num(Sign::Positive, "".as_bytes(), "0".as_bytes())])),
+    parse_test_132763: ("132763", Ok(vec![Instruction::Num(Sign::Positive, "132763".as_bytes(), "".as_bytes())])),
+    parse_test_1: ("1", Ok(vec![Instruction::Num(Sign::Positive, "1".as_bytes(), "".as_bytes())])),
+    parse_test_1dot: ("1.", Ok(vec![Instruction::Num(Sign::Positive, "1".as_bytes(), "".as_bytes())])),
+    parse_test_dot1: (".1", Ok(vec![Instruction::Num(Sign::Positive, "".as_bytes(), "1".as_bytes())])),
+    parse_test_dot: (".", Ok(vec![Instruction::Num(Sign::Positive, "".as_bytes(), "".as_bytes())])),
+    parse_test_dotdot: ("..", Ok(vec![Instruction::Num(Sign::Positive, "".as_bytes(), "".as_bytes()), Instruction::Num(Sign::Positive, "".as_bytes(), "".as_bytes())])),
+    parse_test_dot_dot: (". .", Ok(vec![Instruction::Num(Sign::Positive, "".as_bytes(), "".as_bytes()), Instruction::Num(Sign::Positive, "".as_bytes(), "".as_bytes())])),
+    parse_test_zero_dot_zero: ("0.0", Ok(vec![Instruction::Num(Sign::Positive, "0".as_bytes(), "0".as_bytes())])),
+    parse_test_00: ("00", Ok(vec![Instruction::Num(Sign::Positive, "00".as_bytes(), "".as_bytes())])),
+    parse_test_11: ("11", Ok(vec![Instruction::Num(Sign::Positive, "11".as_bytes(), "".as_bytes())])),
+    parse_test_a2_dot_1: ("A.1", Ok(vec![Instruction::Num(Sign::Positive, "A".as_bytes(), "1".as_bytes())])),
+    parse_test_neg5: ("_5", Ok(vec![Instruction::Num(Sign::Negative, "5".as_bytes(), "".as_bytes())])),
+    parse_test_neg_dot25: ("_.25", Ok(vec![Instruction::Num(Sign::Negative, "".as_bytes(), "25".as_bytes())])),
+    parse_test_neg_a2_dot_1: ("_A.1", Ok(vec![Instruction::Num(Sign::Negative, "A".as_bytes(), "1".as_bytes())])),
+    parse_test_neg_alone: ("_", Ok(vec![Instruction::Num(Sign::Negative, "".as_bytes(), "".as_bytes())])),
+    parse_test_1_neg2: ("1_2", Ok(vec![Instruction::Num(Sign::Positive, "1".as_bytes(), "".as_bytes()), Instruction::Num(Sign::Negative, "2".as_bytes(), "".as_bytes())])),
+    parse_test_0_0: ("0 0", Ok(vec![Instruction::Num(Sign::Positive, "0".as_bytes(), "".as_bytes()), Instruction::Num(Sign::Positive, "0".as_bytes(), "".as_bytes())])),
+    parse_test_1_1: ("1 1", Ok(vec![Instruction::Num(Sign::Positive, "1".as_bytes(), "".as_bytes()), Instruction::Num(Sign::Positive, "1".as_bytes(), "".as_bytes())])),
     parse_test_la: ("la", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::Load, b'a' as Register)])),
     parse_test_sa: ("sa", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::Store, b'a' as Register)])),
     parse_test_l2a: ("La", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::LoadStack, b'a' as Register)])),
@@ -532,24 +657,28 @@ parse_tests! {
     parse_test_lta: ("<a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosLtExecute, b'a' as Register)])),
     parse_test_gta: (">a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosGtExecute, b'a' as Register)])),
     parse_test_eqa: ("=a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosEqExecute, b'a' as Register)])),
-    parse_test_lea: ("!<a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosLeExecute, b'a' as Register)])),
-    parse_test_gea: ("!>a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosGeExecute, b'a' as Register)])),
+    parse_test_lea: ("!>a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosLeExecute, b'a' as Register)])),
+    parse_test_gea: ("!<a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosGeExecute, b'a' as Register)])),
     parse_test_nea: ("!=a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosNeExecute, b'a' as Register)])),
     parse_test_sysa: ("!a", Ok(vec![Instruction::System("a".as_bytes())])),
-    parse_test_sysa10: ("!a\n10", Ok(vec![Instruction::System("a".as_bytes()), Instruction::Num("10".as_bytes(), "".as_bytes())])),
+    parse_test_sysa10: ("!a\n10", Ok(vec![Instruction::System("a".as_bytes()), Instruction::Num(Sign::Positive, "10".as_bytes(), "".as_bytes())])),
     parse_test_ltagt: ("<>", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::TosLtExecute, b'>' as Register)])),
-    parse_test_str_aa3: ("[aa]3", Ok(vec![Instruction::Str("aa".as_bytes()), Instruction::Num("3".as_bytes(), "".as_bytes())])),
+    parse_test_str_aa3: ("[aa]3", Ok(vec![Instruction::Str("aa".as_bytes()), Instruction::Num(Sign::Positive, "3".as_bytes(), "".as_bytes())])),
     parse_test_str_aa: ("[aa]", Ok(vec![Instruction::Str("aa".as_bytes())])),
     parse_test_str_empty: ("[]", Ok(vec![Instruction::Str(&[])])),
     parse_test_str_aanl: ("[aa\n]", Ok(vec![Instruction::Str("aa\n".as_bytes())])),
     parse_test_str_quoteaanl: ("[!aa\n]", Ok(vec![Instruction::Str("!aa\n".as_bytes())])),
     parse_test_str_aa_not_term: ("[aa", Ok(vec![Instruction::Str("aa".as_bytes())])),
+    parse_test_str_nested: ("[[inner]x]", Ok(vec![Instruction::Str("[inner]x".as_bytes())])),
+    parse_test_str_nested_deep: ("[a[b[c]d]e]p", Ok(vec![Instruction::Str("a[b[c]d]e".as_bytes()), Instruction::PrintLN])),
+    parse_test_str_nested_empty: ("[[]]", Ok(vec![Instruction::Str("[]".as_bytes())])),
+    parse_test_str_nested_not_term: ("[[a]", Ok(vec![Instruction::Str("[a]".as_bytes())])),
     parse_test_a: ("a", Ok(vec![Instruction::OpToString])),
     parse_test_z2: ("Z", Ok(vec![Instruction::Digits])),
     parse_test_x2: ("X", Ok(vec![Instruction::FractionDigits])),
     parse_test_z: ("z", Ok(vec![Instruction::StackDepth])),
-    parse_test_comment1: ("10 # foo 20", Ok(vec![Instruction::Num("10".as_bytes(), "".as_bytes()), Instruction::Comment(" foo 20".as_bytes())])),
-    parse_test_comment2: ("10 # foo\n20", Ok(vec![Instruction::Num("10".as_bytes(), "".as_bytes()), Instruction::Comment(" foo".as_bytes()), Instruction::Num("20".as_bytes(), "".as_bytes())])),
+    parse_test_comment1: ("10 # foo 20", Ok(vec![Instruction::Num(Sign::Positive, "10".as_bytes(), "".as_bytes()), Instruction::Comment(" foo 20".as_bytes())])),
+    parse_test_comment2: ("10 # foo\n20", Ok(vec![Instruction::Num(Sign::Positive, "10".as_bytes(), "".as_bytes()), Instruction::Comment(" foo".as_bytes()), Instruction::Num(Sign::Positive, "20".as_bytes(), "".as_bytes())])),
     parse_test_set_array: (":a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::SetArray, b'a' as Register)])),
     parse_test_get_array: (";a", Ok(vec![Instruction::RegisterOperation(RegisterOperationType::GetArray, b'a' as Register)])),
     parse_test_input: ("?", Ok(vec![Instruction::ExecuteInput])),
@@ -573,6 +702,44 @@ fn testparse_invalid() {
     }
 }
 
+#[test]
+fn test_parse_instruction() {
+    let input: &[u8] = b" 12p [a[b]]x _1.5\n#c\nla ";
+    let mut instructions = Vec::new();
+    let mut position = 0;
+    while let Some((instruction, length)) = parse_instruction(&input[position..]).unwrap() {
+        instructions.push(instruction);
+        position += length;
+    }
+    assert_eq!(parse(input).unwrap().instructions, instructions);
+    assert_eq!(input.len(), position);
+    assert_eq!(Some((Instruction::PrintLN, 4)), parse_instruction(b" p \nf").unwrap());
+    assert_eq!(None, parse_instruction(b" \n").unwrap());
+}
+
+#[test]
+fn test_open_brackets() {
+    assert_eq!(0, open_brackets(b"1p [a]sa\n", 0));
+    assert_eq!(1, open_brackets(b"[a\n", 0));
+    assert_eq!(2, open_brackets(b"1 [a[b\n", 0));
+    assert_eq!(1, open_brackets(b"[", 0));
+    assert_eq!(0, open_brackets(b"s[ #[\n", 0));
+    assert_eq!(1, open_brackets(b"] [a[b]\n", 0));
+    assert_eq!(1, open_brackets(b"b]\n", 2));
+    assert_eq!(0, open_brackets(b"b]]p\n", 2));
+    assert_eq!(1, open_brackets(b"b]]p [c\n", 2));
+    assert_eq!(3, open_brackets(b"\n", 3));
+    assert_eq!(0, open_brackets(b"1 s", 0));
+}
+
+#[test]
+fn test_parse_instruction_invalid() {
+    assert_eq!(Some((Instruction::PrintLN, 2)), parse_instruction(b" p]f").unwrap());
+    let parse_error = parse_instruction(b"]f").unwrap_err();
+    assert_eq!(b"f", parse_error.unparsed);
+    assert!(parse_error.program.instructions.is_empty());
+}
+
 
 macro_rules! parse_reversibility {
     ($($name:ident: $value:expr,)*) => {
@@ -596,7 +763,9 @@ macro_rules! parse_reversibility {
 parse_reversibility! {
     rev_empty: "",
     zero: "\0",
+    nested_string: "[[a]sb[c]]x",
     n: "123",
+    negative_n: "_12.5",
     pln: "p",
     pln2: "P",
     pln3: "n",
@@ -606,6 +775,7 @@ parse_reversibility! {
     la: "la",
     Sa: "Sa",
     La: "La",
+    conditionals: "<a>a=a!<a!>a!=a",
     radix_and_prexision: "iokIOK",
     more: "ax?qQZXz",
     comment: "#foo\n",
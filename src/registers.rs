@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use dcstack::{DCError, MemoryCell};
+use instructions::Register;
+
+const REGISTER_COUNT: usize = 256;
+
+// every level of a register stack has its own (sparse) array
+#[derive(Debug, Default)]
+struct RegisterCell {
+    // a cell can exist without a value, e.g., when only its array was used
+    value: Option<MemoryCell>,
+    array: HashMap<u64, MemoryCell>,
+}
+
+impl RegisterCell {
+    fn with_value(value: MemoryCell) -> RegisterCell {
+        RegisterCell {
+            value: Some(value),
+            array: HashMap::new(),
+        }
+    }
+}
+
+/// The 256 dc registers, each one a stack of cells.
+#[derive(Debug)]
+pub struct Registers {
+    registers: Vec<Vec<RegisterCell>>,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        let mut registers = Vec::with_capacity(REGISTER_COUNT);
+        for _ in 0..REGISTER_COUNT {
+            registers.push(Vec::new());
+        }
+        Registers { registers }
+    }
+
+    fn register_stack(&self, register: Register) -> &Vec<RegisterCell> {
+        &self.registers[register as usize]
+    }
+
+    fn register_stack_mut(&mut self, register: Register) -> &mut Vec<RegisterCell> {
+        &mut self.registers[register as usize]
+    }
+
+    /// `s`: replaces the top of the register stack (or creates it).
+    pub fn store(&mut self, register: Register, value: MemoryCell) {
+        let register_stack = self.register_stack_mut(register);
+        match register_stack.last_mut() {
+            Some(cell) => cell.value = Some(value),
+            None => register_stack.push(RegisterCell::with_value(value)),
+        }
+    }
+
+    /// `l`: copies the top of the register stack.
+    pub fn load(&self, register: Register) -> Result<MemoryCell, DCError> {
+        match self.register_stack(register).last() {
+            Some(&RegisterCell {
+                value: Some(ref value),
+                ..
+            }) => Ok(value.clone()),
+            _ => Err(DCError::RegisterEmpty(register)),
+        }
+    }
+
+    /// `S`: pushes a new cell on the register stack.
+    pub fn push(&mut self, register: Register, value: MemoryCell) {
+        self.register_stack_mut(register)
+            .push(RegisterCell::with_value(value));
+    }
+
+    /// `L`: pops the top of the register stack.
+    pub fn pop(&mut self, register: Register) -> Result<MemoryCell, DCError> {
+        let register_stack = self.register_stack_mut(register);
+        // dc leaves the register untouched if there is nothing to pop
+        match register_stack.last() {
+            Some(&RegisterCell { value: Some(..), .. }) => {}
+            _ => return Err(DCError::StackRegisterEmpty(register)),
+        }
+        Ok(register_stack
+            .pop()
+            .and_then(|cell| cell.value)
+            .expect("checked above"))
+    }
+
+    /// `:`: sets an element of the array of the top of the register stack.
+    pub fn set_array(&mut self, register: Register, index: u64, value: MemoryCell) {
+        let register_stack = self.register_stack_mut(register);
+        if register_stack.is_empty() {
+            register_stack.push(RegisterCell::default());
+        }
+        if let Some(cell) = register_stack.last_mut() {
+            cell.array.insert(index, value);
+        }
+    }
+
+    /// `;`: gets an element of the array of the top of the register stack,
+    /// elements never set are 0.
+    pub fn get_array(&self, register: Register, index: u64) -> MemoryCell {
+        self.register_stack(register)
+            .last()
+            .and_then(|cell| cell.array.get(&index))
+            .cloned()
+            .unwrap_or_else(|| MemoryCell::from(0))
+    }
+}
+
+#[test]
+fn test_load_empty() {
+    let registers = Registers::new();
+    assert_eq!(
+        Err(DCError::RegisterEmpty(b'a')),
+        registers.load(b'a')
+    );
+}
+
+#[test]
+fn test_store_load() {
+    let mut registers = Registers::new();
+    registers.store(b'a', MemoryCell::from(1));
+    registers.store(b'a', MemoryCell::from(2));
+    assert_eq!(Ok(MemoryCell::from(2)), registers.load(b'a'));
+    assert_eq!(Ok(MemoryCell::from(2)), registers.pop(b'a'));
+    assert_eq!(
+        Err(DCError::StackRegisterEmpty(b'a')),
+        registers.pop(b'a')
+    );
+}
+
+#[test]
+fn test_push_pop() {
+    let mut registers = Registers::new();
+    registers.push(b'a', MemoryCell::from(1));
+    registers.push(b'a', MemoryCell::from(2));
+    assert_eq!(Ok(MemoryCell::from(2)), registers.pop(b'a'));
+    assert_eq!(Ok(MemoryCell::from(1)), registers.load(b'a'));
+    assert_eq!(Err(DCError::RegisterEmpty(b'b')), registers.load(b'b'));
+}
+
+#[test]
+fn test_array() {
+    let mut registers = Registers::new();
+    assert_eq!(MemoryCell::from(0), registers.get_array(b'a', 10));
+    registers.set_array(b'a', 10, MemoryCell::from(3));
+    registers.set_array(b'a', u64::max_value(), MemoryCell::from(4));
+    assert_eq!(MemoryCell::from(3), registers.get_array(b'a', 10));
+    assert_eq!(MemoryCell::from(4), registers.get_array(b'a', u64::max_value()));
+    assert_eq!(MemoryCell::from(0), registers.get_array(b'a', 11));
+    // the array alone does not give the register a value
+    assert_eq!(Err(DCError::RegisterEmpty(b'a')), registers.load(b'a'));
+}
+
+#[test]
+fn test_array_scoping() {
+    let mut registers = Registers::new();
+    registers.set_array(b'a', 0, MemoryCell::from(1));
+    registers.store(b'a', MemoryCell::from(10));
+    registers.push(b'a', MemoryCell::from(20));
+    assert_eq!(MemoryCell::from(0), registers.get_array(b'a', 0));
+    registers.set_array(b'a', 0, MemoryCell::from(2));
+    assert_eq!(Ok(MemoryCell::from(20)), registers.pop(b'a'));
+    assert_eq!(MemoryCell::from(1), registers.get_array(b'a', 0));
+}
@@ -0,0 +1,211 @@
+#[cfg(feature = "repl")]
+use std::cell::RefCell;
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+#[cfg(feature = "repl")]
+use std::rc::Rc;
+
+#[cfg(feature = "repl")]
+use vm::{LineSource, ReturnState};
+use vm::VM;
+
+const HISTORY_FILE: &str = ".rdc_history";
+// the characters of the top of the stack shown by the prompt
+const TOP_WIDTH: usize = 20;
+
+/// What the interactive prompt shows.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Prompt {
+    Plain,
+    /// The number of values on the stack.
+    Depth,
+    /// The value on top of the stack.
+    Top,
+}
+
+impl Prompt {
+    pub fn from_name(name: &str) -> Option<Prompt> {
+        match name {
+            "plain" => Some(Prompt::Plain),
+            "depth" => Some(Prompt::Depth),
+            "top" => Some(Prompt::Top),
+            _ => None,
+        }
+    }
+
+    pub fn render<R, W, E>(&self, vm: &VM<R, W, E>) -> String
+    where
+        R: BufRead,
+        W: Write,
+        E: Write,
+    {
+        match *self {
+            Prompt::Plain => "> ".to_string(),
+            Prompt::Depth => format!("[{}]> ", vm.stack_depth()),
+            Prompt::Top => format!("[{}]> ", abbreviate(&vm.top_of_stack().unwrap_or_default())),
+        }
+    }
+}
+
+// keeps the prompt on one short line: control characters are escaped and
+// what does not fit is cut
+fn abbreviate(value: &str) -> String {
+    let mut escaped = value.chars().flat_map(|c| {
+        let escape: Vec<char> = if c.is_control() {
+            c.escape_default().collect()
+        } else {
+            vec![c]
+        };
+        escape
+    });
+    let mut abbreviated: String = escaped.by_ref().take(TOP_WIDTH).collect();
+    if escaped.next().is_some() {
+        abbreviated.push_str("...");
+    }
+    abbreviated
+}
+
+/// `~/.rdc_history`, if there is a home.
+pub fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Runs a line of the session, errors are reported and the session goes on.
+/// Returns false when the line quits the session with `q`, run there or in a
+/// macro it calls.
+pub fn eval_line<R, W, E>(vm: &mut VM<R, W, E>, line: &str) -> io::Result<bool>
+where
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    let state = vm.execute(line.as_bytes())?;
+    vm.flush()?;
+    Ok(!state.quits())
+}
+
+#[cfg(feature = "repl")]
+fn to_io_error(error: ::rustyline::error::ReadlineError) -> io::Error {
+    match error {
+        ::rustyline::error::ReadlineError::Io(error) => error,
+        error => io::Error::other(error.to_string()),
+    }
+}
+
+// `?` reads from the editor too, the terminal has a single reader
+#[cfg(feature = "repl")]
+struct EditorLines(Rc<RefCell<::rustyline::DefaultEditor>>);
+
+#[cfg(feature = "repl")]
+impl LineSource for EditorLines {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        use rustyline::error::ReadlineError;
+
+        match self.0.borrow_mut().readline("") {
+            Ok(text) => {
+                line.extend_from_slice(text.as_bytes());
+                line.push(b'\n');
+                Ok(text.len() + 1)
+            }
+            Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => Ok(0),
+            Err(error) => Err(to_io_error(error)),
+        }
+    }
+}
+
+/// Reads lines from the terminal, with editing and history, until end of
+/// file or `q`, which returns a quitting state.
+#[cfg(feature = "repl")]
+pub fn run<R, W, E>(vm: &mut VM<R, W, E>, prompt: Prompt) -> io::Result<ReturnState>
+where
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    use rustyline::error::ReadlineError;
+
+    let editor = Rc::new(RefCell::new(
+        ::rustyline::DefaultEditor::new().map_err(to_io_error)?,
+    ));
+    let history = history_path();
+    if let Some(ref path) = history {
+        // there is no history the first time
+        let _ = editor.borrow_mut().load_history(path);
+    }
+    vm.set_line_source(Some(Box::new(EditorLines(editor.clone()))));
+    let result = loop {
+        let read = editor.borrow_mut().readline(&prompt.render(vm));
+        match read {
+            Ok(line) => {
+                let _ = editor.borrow_mut().add_history_entry(line.as_str());
+                match eval_line(vm, &line) {
+                    Ok(true) => continue,
+                    Ok(false) => break Ok(ReturnState::TerminatingReturn),
+                    Err(error) => break Err(error),
+                }
+            }
+            // ^C discards the line, as in a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break Ok(ReturnState::Done),
+            Err(error) => break Err(to_io_error(error)),
+        }
+    };
+    vm.set_line_source(None);
+    if let Some(ref path) = history {
+        if let Err(error) = editor.borrow_mut().save_history(path) {
+            vm.report_error(format!("cannot save history: {}", error))?;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+type InMemoryVM = VM<io::Cursor<Vec<u8>>, Vec<u8>, Vec<u8>>;
+
+#[test]
+fn test_prompt_from_name() {
+    assert_eq!(Some(Prompt::Plain), Prompt::from_name("plain"));
+    assert_eq!(Some(Prompt::Depth), Prompt::from_name("depth"));
+    assert_eq!(Some(Prompt::Top), Prompt::from_name("top"));
+    assert_eq!(None, Prompt::from_name("fancy"));
+}
+
+#[test]
+fn test_prompt_render() {
+    let mut vm = InMemoryVM::default();
+    assert_eq!("> ", Prompt::Plain.render(&vm));
+    assert_eq!("[0]> ", Prompt::Depth.render(&vm));
+    assert_eq!("[]> ", Prompt::Top.render(&vm));
+    assert!(eval_line(&mut vm, "1 2.5").unwrap());
+    assert_eq!("[2]> ", Prompt::Depth.render(&vm));
+    assert_eq!("[2.5]> ", Prompt::Top.render(&vm));
+    assert!(eval_line(&mut vm, "[two\nlines] 2 100^").unwrap());
+    assert_eq!("[12676506002282294014...]> ", Prompt::Top.render(&vm));
+    assert!(eval_line(&mut vm, "r").unwrap());
+    assert_eq!("[two\\nlines]> ", Prompt::Top.render(&vm));
+    assert!(eval_line(&mut vm, "255a").unwrap());
+    assert_eq!("[\u{fffd}]> ", Prompt::Top.render(&vm));
+}
+
+#[test]
+fn test_eval_line_keeps_going_after_errors() {
+    let mut vm = InMemoryVM::default();
+    assert!(eval_line(&mut vm, "1 +").unwrap());
+    assert!(eval_line(&mut vm, "2+p").unwrap());
+    assert!(!eval_line(&mut vm, "q 4p").unwrap());
+    let (output, error) = vm.sinks();
+    assert_eq!("3\n", String::from_utf8(output).unwrap());
+    assert_eq!("dc: stack empty\n", String::from_utf8(error).unwrap());
+}
+
+#[test]
+fn test_eval_line_quit_in_macro() {
+    let mut vm = InMemoryVM::default();
+    assert!(!eval_line(&mut vm, "[q]x").unwrap());
+    assert!(!eval_line(&mut vm, "[q]x 5p").unwrap());
+    assert!(eval_line(&mut vm, "[[q]x]x 5p").unwrap());
+    let (output, _) = vm.sinks();
+    assert_eq!("5\n", String::from_utf8(output).unwrap());
+}
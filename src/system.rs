@@ -0,0 +1,158 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs the commands of `!`, embedders can provide their own to sandbox or
+/// forbid process spawning.
+pub trait CommandRunner: fmt::Debug {
+    /// Runs `command`, writing its standard output and error to `stdout`
+    /// and `stderr`.
+    fn run(
+        &mut self,
+        command: &[u8],
+        stdout: &mut dyn Write,
+        stderr: &mut dyn Write,
+    ) -> io::Result<()>;
+}
+
+/// Runs commands with `/bin/sh -c`, like dc does. The standard input of the
+/// child is closed, since the input of the VM may not be the process one.
+/// Its output is copied to the sinks while it runs, in the order it comes.
+#[derive(Debug, Default)]
+pub struct ShellCommandRunner;
+
+// the command is passed to the shell as it is written, bytes included
+#[cfg(unix)]
+fn shell_argument(command: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    ::std::ffi::OsStr::from_bytes(command).to_os_string()
+}
+
+#[cfg(not(unix))]
+fn shell_argument(command: &[u8]) -> OsString {
+    OsString::from(String::from_utf8_lossy(command).into_owned())
+}
+
+#[derive(Clone, Copy)]
+enum Pipe {
+    Stdout,
+    Stderr,
+}
+
+// reads a pipe of the child until it closes, sending what it reads
+fn forward<R>(
+    mut pipe: R,
+    name: Pipe,
+    sender: mpsc::Sender<(Pipe, Vec<u8>)>,
+) -> thread::JoinHandle<io::Result<()>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let read = pipe.read(&mut buffer)?;
+            // the receiver is gone when writing to a sink failed
+            if read == 0 || sender.send((name, buffer[..read].to_vec())).is_err() {
+                return Ok(());
+            }
+        }
+    })
+}
+
+fn write_flushed(sink: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    sink.write_all(bytes)?;
+    sink.flush()
+}
+
+impl CommandRunner for ShellCommandRunner {
+    fn run(
+        &mut self,
+        command: &[u8],
+        stdout: &mut dyn Write,
+        stderr: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(shell_argument(command))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (sender, receiver) = mpsc::channel();
+        let readers = vec![
+            forward(child.stdout.take().expect("piped stdout"), Pipe::Stdout, sender.clone()),
+            forward(child.stderr.take().expect("piped stderr"), Pipe::Stderr, sender),
+        ];
+        let mut copied = Ok(());
+        for (name, bytes) in receiver {
+            copied = match name {
+                Pipe::Stdout => write_flushed(stdout, &bytes),
+                Pipe::Stderr => write_flushed(stderr, &bytes),
+            };
+            if copied.is_err() {
+                break;
+            }
+        }
+        for reader in readers {
+            let read = reader.join().expect("pipe reader panicked");
+            copied = copied.and(read);
+        }
+        child.wait()?;
+        copied
+    }
+}
+
+/// Refuses to run any command.
+#[derive(Debug, Default)]
+pub struct DisabledCommandRunner;
+
+impl CommandRunner for DisabledCommandRunner {
+    fn run(
+        &mut self,
+        _command: &[u8],
+        _stdout: &mut dyn Write,
+        _stderr: &mut dyn Write,
+    ) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "running commands is disabled",
+        ))
+    }
+}
+
+#[test]
+fn test_shell_command_runner() {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    ShellCommandRunner
+        .run(b"echo out; echo err >&2", &mut stdout, &mut stderr)
+        .expect("sh should run");
+    assert_eq!(b"out\n".to_vec(), stdout);
+    assert_eq!(b"err\n".to_vec(), stderr);
+}
+
+#[test]
+fn test_shell_command_runner_bytes() {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    ShellCommandRunner
+        .run(b"printf '%s' '\xff\xfe'", &mut stdout, &mut stderr)
+        .expect("sh should run");
+    assert_eq!(vec![0xff, 0xfe], stdout);
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn test_disabled_command_runner() {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    assert!(DisabledCommandRunner
+        .run(b"echo out", &mut stdout, &mut stderr)
+        .is_err());
+    assert!(stdout.is_empty());
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::convert::From;
 use std::error;
 use std::error::Error;
@@ -5,14 +6,18 @@ use std::fmt;
 use std::io;
 use std::io::prelude::*;
 use std::ops::*;
+use std::rc::Rc;
 
 use bigdecimal::BigDecimal;
-use bigdecimal::FromPrimitive;
 use bigdecimal::ToPrimitive;
 
 use dcstack;
 use instructions::*;
+use numeric;
+use parameters;
 use parse;
+use registers;
+use system;
 
 #[derive(Debug)]
 pub enum VMState {
@@ -26,31 +31,38 @@ pub enum VMState {
     TerminatingReturn,
     TerminatingReturnEnclosing,
     NonTerminatingReturn(u64),
+    CommandError(String),
+    Aborted,
+    // a frame was pushed, the VM runs it next
+    Call,
 }
 
-static CONTINUE: &'static str = "continue";
-static INVALID_INPUT_RADIX: &'static str = "invalid input radix";
-static INVALID_OUTPUT_RADIX: &'static str = "invalid output radix";
-static INVALID_PRECISION: &'static str = "invalid precision";
-static NOT_IMPLEMENTED: &'static str = "not implemented";
-static TERMINATING_RETURN: &'static str = "terminating return";
-static TERMINATING_RETURN_ENCLOSING: &'static str = "terminating return enclosing";
-static NON_TERMINATING_RETURN: &'static str = "non terminating return";
-static BAD_Q_NUMBER: &'static str = "Q command requires a number >= 1";
+static CONTINUE: &str = "continue";
+static NOT_IMPLEMENTED: &str = "not implemented";
+static TERMINATING_RETURN: &str = "terminating return";
+static TERMINATING_RETURN_ENCLOSING: &str = "terminating return enclosing";
+static NON_TERMINATING_RETURN: &str = "non terminating return";
+static BAD_Q_NUMBER: &str = "Q command requires a number >= 1";
+static COMMAND_ERROR: &str = "command error";
+static ABORTED: &str = "aborted";
+static CALL: &str = "call";
 
 impl VMState {
-    fn message(&self) -> &'static str {
-        match self {
-            &VMState::Continue => &CONTINUE,
-            &VMState::InvalidInputRadix => &INVALID_INPUT_RADIX,
-            &VMState::InvalidOutputRadix => &INVALID_OUTPUT_RADIX,
-            &VMState::InvalidPrecision => &INVALID_PRECISION,
-            &VMState::NotImplemented => &NOT_IMPLEMENTED,
-            &VMState::InvalidCallStackOperation => &BAD_Q_NUMBER,
-            &VMState::TerminatingReturn => &TERMINATING_RETURN,
-            &VMState::TerminatingReturnEnclosing => &TERMINATING_RETURN_ENCLOSING,
-            &VMState::NonTerminatingReturn(..) => &NON_TERMINATING_RETURN,
-            &VMState::StackError(dcerror) => dcerror.message(),
+    fn message(&self) -> &str {
+        match *self {
+            VMState::Continue => CONTINUE,
+            VMState::InvalidInputRadix => parameters::ParameterError::InputRadix.message(),
+            VMState::InvalidOutputRadix => parameters::ParameterError::OutputRadix.message(),
+            VMState::InvalidPrecision => parameters::ParameterError::Precision.message(),
+            VMState::NotImplemented => NOT_IMPLEMENTED,
+            VMState::InvalidCallStackOperation => BAD_Q_NUMBER,
+            VMState::TerminatingReturn => TERMINATING_RETURN,
+            VMState::TerminatingReturnEnclosing => TERMINATING_RETURN_ENCLOSING,
+            VMState::NonTerminatingReturn(..) => NON_TERMINATING_RETURN,
+            VMState::StackError(ref dcerror) => dcerror.message(),
+            VMState::CommandError(..) => COMMAND_ERROR,
+            VMState::Aborted => ABORTED,
+            VMState::Call => CALL,
         }
     }
 
@@ -68,6 +80,7 @@ impl VMState {
     fn is_ok(&self) -> bool {
         match self {
             &VMState::Continue
+            | &VMState::Call
             | &VMState::NonTerminatingReturn(..)
             | &VMState::TerminatingReturnEnclosing
             | &VMState::TerminatingReturn => true,
@@ -96,6 +109,17 @@ impl From<Result<(), dcstack::DCError>> for VMState {
     }
 }
 
+impl From<Result<(), parameters::ParameterError>> for VMState {
+    fn from(result: Result<(), parameters::ParameterError>) -> VMState {
+        match result {
+            Ok(()) => VMState::Continue,
+            Err(parameters::ParameterError::InputRadix) => VMState::InvalidInputRadix,
+            Err(parameters::ParameterError::OutputRadix) => VMState::InvalidOutputRadix,
+            Err(parameters::ParameterError::Precision) => VMState::InvalidPrecision,
+        }
+    }
+}
+
 impl From<Result<VMState, dcstack::DCError>> for VMState {
     fn from(result: Result<VMState, dcstack::DCError>) -> VMState {
         match result {
@@ -107,7 +131,11 @@ impl From<Result<VMState, dcstack::DCError>> for VMState {
 
 impl fmt::Display for VMState {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{}", self.message())?;
+        match self {
+            &VMState::StackError(ref dcerror) => write!(f, "{}", dcerror)?,
+            &VMState::CommandError(ref error) => write!(f, "{}: {}", COMMAND_ERROR, error)?,
+            state => write!(f, "{}", state.message())?,
+        }
         Ok(())
     }
 }
@@ -162,6 +190,8 @@ pub enum ReturnState {
     TerminatingReturn,
     TerminatingReturnEnclosing,
     NonTerminatingReturn(u64),
+    // an error in strict mode, it unwinds every macro
+    Aborted,
 }
 
 impl ReturnState {
@@ -172,6 +202,29 @@ impl ReturnState {
             &ReturnState::TerminatingReturnEnclosing => ReturnState::Done,
             &ReturnState::NonTerminatingReturn(0) => ReturnState::Done,
             &ReturnState::NonTerminatingReturn(n) => ReturnState::NonTerminatingReturn(n - 1),
+            &ReturnState::Aborted => ReturnState::Aborted,
+        }
+    }
+
+    // the state once `levels` macros are left
+    fn leave(&self, levels: u64) -> Self {
+        let mut state = *self;
+        for _ in 0..levels {
+            if !state.terminates_exec() || state == ReturnState::Aborted {
+                break;
+            }
+            state = state.next();
+        }
+        state
+    }
+
+    /// Whether a `q` ends the program: it was run at the top level or in a
+    /// macro called from there.
+    pub fn quits(&self) -> bool {
+        match self {
+            &ReturnState::TerminatingReturn => true,
+            &ReturnState::TerminatingReturnEnclosing => true,
+            _other => false,
         }
     }
 
@@ -181,6 +234,7 @@ impl ReturnState {
             &ReturnState::TerminatingReturn => true,
             &ReturnState::TerminatingReturnEnclosing => true,
             &ReturnState::NonTerminatingReturn(_n) => true,
+            &ReturnState::Aborted => true,
         }
     }
 }
@@ -198,6 +252,7 @@ impl fmt::Display for ReturnState {
             &ReturnState::TerminatingReturn => f.write_str("TerminatingReturn"),
             &ReturnState::TerminatingReturnEnclosing => f.write_str("TerminatingReturnEnclosing"),
             &ReturnState::NonTerminatingReturn(n) => write!(f, "NonTerminatingReturn({})", n),
+            &ReturnState::Aborted => f.write_str("Aborted"),
         }?)
     }
 }
@@ -208,6 +263,7 @@ impl From<VMState> for ReturnState {
             VMState::NonTerminatingReturn(n) => ReturnState::NonTerminatingReturn(n),
             VMState::TerminatingReturn => ReturnState::TerminatingReturn,
             VMState::TerminatingReturnEnclosing => ReturnState::TerminatingReturnEnclosing,
+            VMState::Aborted => ReturnState::Aborted,
             _other => ReturnState::Done,
         }
     }
@@ -220,23 +276,95 @@ impl From<ReturnState> for VMState {
             ReturnState::NonTerminatingReturn(n) => VMState::NonTerminatingReturn(n),
             ReturnState::TerminatingReturn => VMState::TerminatingReturn,
             ReturnState::TerminatingReturnEnclosing => VMState::TerminatingReturnEnclosing,
+            ReturnState::Aborted => VMState::Aborted,
         }
     }
 }
 
+/// The width at which dc wraps numbers, unless `DC_LINE_LENGTH` says otherwise.
+pub const DEFAULT_LINE_LENGTH: usize = 70;
+
+/// Parses the value of `DC_LINE_LENGTH` like dc, which reads it with
+/// `atoi`: the digits after the leading blanks and sign count, so anything
+/// that is not a number is 0. Values below 2, negative ones included,
+/// disable wrapping.
+pub fn parse_line_length(value: &str) -> usize {
+    let value = value.trim_start();
+    let (negative, unsigned) = match value.as_bytes().first() {
+        Some(&b'-') => (true, &value[1..]),
+        Some(&b'+') => (false, &value[1..]),
+        _ => (false, value),
+    };
+    if negative {
+        return 0;
+    }
+    let end = unsigned
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(unsigned.len());
+    let digits = &unsigned[..end];
+    match digits.parse::<usize>() {
+        Ok(n) if n < 2 => 0,
+        Ok(n) => n,
+        // too long to ever wrap
+        Err(..) if !digits.is_empty() => usize::MAX,
+        Err(..) => 0,
+    }
+}
+
+/// Where `?` reads its lines from instead of the input of the VM, like the
+/// line editor of an interactive session.
+pub trait LineSource {
+    /// Appends the next line, with its newline, to `line`; reads nothing at
+    /// the end of the input.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize>;
+}
+
+// a macro, or some text run in place, being executed
 #[derive(Debug)]
-pub struct VM<W, WE>
+struct Frame {
+    text: Rc<[u8]>,
+    // where the next instruction is parsed from, each byte is parsed once
+    position: usize,
+    // the macro levels left when the frame finishes: none for text run in
+    // place, more than one once tail calls replaced the frame
+    levels: u64,
+}
+
+impl Frame {
+    fn new<T: Into<Rc<[u8]>>>(text: T, levels: u64) -> Frame {
+        Frame {
+            text: text.into(),
+            position: 0,
+            levels,
+        }
+    }
+
+    // whether no instruction is left after the running one: the parser skips
+    // the blanks after each instruction; a parse error left counts as an
+    // instruction, it is reported when the frame resumes
+    fn finished(&self) -> bool {
+        self.position == self.text.len()
+    }
+}
+
+pub struct VM<R, W, WE>
 where
+    R: BufRead,
     W: Write,
     WE: Write,
 {
     stack: dcstack::DCStack,
-    input_radix: u32,  // [2,16]
-    output_radix: u32, // >= 2
-    precision: u64,    // > 0, always in decimal
+    registers: registers::Registers,
+    parameters: parameters::Parameters,
+    line_length: usize, // 0 disables wrapping
+    input: R,
     sink: W,
     error_sink: WE,
-    macro_level: u64,
+    command_runner: Box<dyn system::CommandRunner>,
+    line_source: Option<Box<dyn LineSource>>,
+    frames: Vec<Frame>,
+    strict: bool,
+    errors: u64,
 }
 
 macro_rules! bin_op {
@@ -249,41 +377,107 @@ macro_rules! bin_op {
     }};
 }
 
-impl<W, WE> Default for VM<W, WE>
+impl<R, W, WE> Default for VM<R, W, WE>
 where
+    R: BufRead + Default,
     W: Write + Default,
     WE: Write + Default,
 {
-    fn default() -> VM<W, WE> {
+    fn default() -> VM<R, W, WE> {
         VM {
             stack: dcstack::DCStack::new(),
-            input_radix: 10,
-            output_radix: 10,
-            precision: 0,
+            registers: registers::Registers::new(),
+            parameters: parameters::Parameters::default(),
+            line_length: DEFAULT_LINE_LENGTH,
+            input: R::default(),
             sink: W::default(),
             error_sink: WE::default(),
-            macro_level: 0,
+            command_runner: Box::new(system::ShellCommandRunner),
+            line_source: None,
+            frames: Vec::new(),
+            strict: false,
+            errors: 0,
         }
     }
 }
 
-impl<W, WE> VM<W, WE>
+impl<R, W, WE> VM<R, W, WE>
 where
+    R: BufRead,
     W: Write,
     WE: Write,
 {
-    pub fn new(w: W, esink: WE) -> VM<W, WE> {
+    pub fn new(input: R, w: W, esink: WE) -> VM<R, W, WE> {
         VM {
             stack: dcstack::DCStack::new(),
-            input_radix: 10,
-            output_radix: 10,
-            precision: 0,
+            registers: registers::Registers::new(),
+            parameters: parameters::Parameters::default(),
+            line_length: DEFAULT_LINE_LENGTH,
+            input,
             sink: w,
             error_sink: esink,
-            macro_level: 0,
+            command_runner: Box::new(system::ShellCommandRunner),
+            line_source: None,
+            frames: Vec::new(),
+            strict: false,
+            errors: 0,
         }
     }
 
+    /// Replaces how `!` runs commands, by default they go to `/bin/sh`.
+    pub fn set_command_runner<C>(&mut self, command_runner: C)
+    where
+        C: system::CommandRunner + 'static,
+    {
+        self.command_runner = Box::new(command_runner);
+    }
+
+    /// Makes `?` read from `line_source`, `None` goes back to the input.
+    pub fn set_line_source(&mut self, line_source: Option<Box<dyn LineSource>>) {
+        self.line_source = line_source;
+    }
+
+    /// Sets the width at which numbers are wrapped with a `\`, 0 disables it.
+    pub fn set_line_length(&mut self, line_length: usize) {
+        self.line_length = line_length;
+    }
+
+
+    /// In strict mode the first error stops the execution.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// How many errors have been reported so far.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Reports an error that happened outside of the VM, e.g., reading a
+    /// program, as the VM does with its own.
+    pub fn report_error<D: fmt::Display>(&mut self, error: D) -> io::Result<()> {
+        self.errors += 1;
+        writeln!(self.error_sink, "dc: {}", error)
+    }
+
+    /// How many values are on the stack.
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// The top of the stack as `p` would print it, without wrapping.
+    pub fn top_of_stack(&self) -> Option<String> {
+        self.stack
+            .peek()
+            .ok()
+            .map(|tos| tos.to_str_radix(self.parameters.output_radix()))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()?;
+        self.error_sink.flush()
+    }
+
     pub fn sinks(self) -> (W, WE) {
         (self.sink, self.error_sink)
     }
@@ -300,93 +494,163 @@ where
                 where_,
                 i,
                 self.stack,
-                self.input_radix,
-                self.output_radix,
-                self.precision,
-                self.macro_level,
+                self.parameters.input_radix(),
+                self.parameters.output_radix(),
+                self.parameters.precision(),
+                self.frames.len(),
             )?;
             writeln!(out.lock(), "{:?}", ReturnState::NonTerminatingReturn(1))?;
         }
         Ok(())
     }
 
-    fn eval(&mut self, program: &Program) -> Result<ReturnState, io::Error> {
-        for instruction in &program.instructions {
-            self.trace("__enter_eval__", instruction)?;
-            match self.eval_instruction(&instruction) {
-                Err(VMError::IoError(ioerror)) => {
-                    self.trace("__exit_eval__ ioerror", instruction)?;
-                    return Err(ioerror);
-                }
-                Err(error) => {
-                    self.trace("__exit_eval__ error", instruction)?;
-                    writeln!(self.error_sink, "dc: {}", error)?;
+    // evaluates an instruction, errors are reported and abort the execution
+    // in strict mode
+    fn eval(&mut self, instruction: &Instruction) -> Result<VMState, io::Error> {
+        self.trace("__enter_eval__", instruction)?;
+        let vm_state = match self.eval_instruction(instruction) {
+            Err(VMError::IoError(ioerror)) => {
+                self.trace("__exit_eval__ ioerror", instruction)?;
+                return Err(ioerror);
+            }
+            Err(error) => {
+                self.trace("__exit_eval__ error", instruction)?;
+                self.report_error(error)?;
+                return Ok(self.after_error());
+            }
+            Ok(vm_state) => vm_state,
+        };
+        self.trace("__exit_eval__", instruction)?;
+        match vm_state {
+            VMState::Continue
+            | VMState::Call
+            | VMState::TerminatingReturn
+            | VMState::TerminatingReturnEnclosing
+            | VMState::NonTerminatingReturn(..)
+            | VMState::Aborted => Ok(vm_state),
+            error => {
+                // we do not really need to go out here
+                self.report_error(error)?;
+                Ok(self.after_error())
+            }
+        }
+    }
+
+    fn after_error(&self) -> VMState {
+        if self.strict {
+            VMState::Aborted
+        } else {
+            VMState::Continue
+        }
+    }
+
+    pub fn execute(&mut self, program_text: &[u8]) -> Result<ReturnState, io::Error> {
+        let base = self.frames.len();
+        self.frames.push(Frame::new(program_text, 0));
+        let res = self.run(base);
+        if res.is_err() {
+            self.frames.truncate(base);
+        }
+        self.trace("__exit_execute__", &UTF8Adapter { program_text })?;
+        res
+    }
+
+    /// Runs the input a line at a time, as dc does: the output of a line
+    /// comes before the next line is read, and `?` reads the lines that
+    /// follow. A string left open goes on in the next lines.
+    pub fn execute_input(&mut self) -> Result<ReturnState, io::Error> {
+        let mut program_text = Vec::new();
+        let mut open = 0;
+        loop {
+            let start = program_text.len();
+            let read = self.input.read_until(b'\n', &mut program_text)?;
+            open = parse::open_brackets(&program_text[start..], open);
+            if read > 0 && open > 0 {
+                continue;
+            }
+            if program_text.is_empty() {
+                return Ok(ReturnState::Done);
+            }
+            let state = self.execute(&program_text)?;
+            self.flush()?;
+            if read == 0 || state.quits() || state == ReturnState::Aborted {
+                return Ok(state);
+            }
+            program_text.clear();
+        }
+    }
+
+    // runs the frames above base; macros push frames instead of recursing,
+    // so the depth of the calls is not bound by the native stack
+    fn run(&mut self, base: usize) -> Result<ReturnState, io::Error> {
+        while self.frames.len() > base {
+            let (text, position) = {
+                let frame = self.frames.last().expect("a running frame");
+                (frame.text.clone(), frame.position)
+            };
+            if position == 0 {
+                self.trace("__enter_execute__", &UTF8Adapter { program_text: &text })?;
+            }
+            let state = match parse::parse_instruction(&text[position..]) {
+                Ok(Some((instruction, length))) => {
+                    self.frames.last_mut().expect("a running frame").position += length;
+                    match self.eval(&instruction)? {
+                        VMState::Continue | VMState::Call => continue,
+                        vm_state => ReturnState::from(vm_state),
+                    }
                 }
-                Ok(vm_state) => {
-                    self.trace("__exit_eval__", instruction)?;
-                    match vm_state {
-                        VMState::Continue => continue,
-                        r @ VMState::TerminatingReturn
-                        | r @ VMState::TerminatingReturnEnclosing
-                        | r @ VMState::NonTerminatingReturn(..) => return Ok(ReturnState::from(r)),
-                        error => {
-                            // we do not really need to go out here
-                            writeln!(self.error_sink, "dc: {}", error)?;
-                        }
+                Ok(None) => ReturnState::Done,
+                // dc goes on after the characters it cannot parse
+                Err(parse_error) => {
+                    self.report_error(&parse_error)?;
+                    if !self.strict {
+                        let frame = self.frames.last_mut().expect("a running frame");
+                        frame.position = text.len() - parse_error.unparsed.len();
+                        continue;
                     }
+                    ReturnState::Aborted
                 }
             };
+            if let Some(res) = self.unwind(base, state) {
+                return Ok(res);
+            }
         }
         Ok(ReturnState::Done)
     }
 
-    pub fn execute(&mut self, program_text: &[u8]) -> Result<ReturnState, io::Error> {
-        let res = match parse::parse(program_text) {
-            Ok(program) => {
-                self.trace("__enter_execute__", &program)?;
-                self.eval(&program)?
-            }
-            Err(parse_error) => {
-                self.trace("__enter_execute__ parse error", &parse_error.program)?;
-                let eval_res = self.eval(&parse_error.program)?;
-                if eval_res.terminates_exec() {
-                    eval_res
-                } else {
-                    writeln!(self.error_sink, "dc: {}", parse_error)?;
-                    self.execute(parse_error.unparsed)?
-                }
+    // pops the running frame, and its callers while the state says so;
+    // returns the state of the execution once no frame above base is left
+    fn unwind(&mut self, base: usize, mut state: ReturnState) -> Option<ReturnState> {
+        loop {
+            let frame = self.frames.pop().expect("a running frame");
+            state = state.leave(frame.levels);
+            if self.frames.len() == base {
+                return Some(state);
             }
-        };
-        self.trace("__exit_execute__", &UTF8Adapter { program_text })?;
-        Ok(res)
-    }
-
-    fn execute_macro(&mut self, program_text: &[u8]) -> Result<ReturnState, io::Error> {
-        self.macro_level += 1; 
-        match self.execute(program_text) {
-            Ok(result_status) => {
-                self.macro_level -= 1; 
-                Ok(result_status.next())
+            if !state.terminates_exec() {
+                return None;
             }
-            Err(error) => {
-                self.macro_level -= 1; 
-                Err(error)
-            },
         }
     }
 
-    #[inline]
-    fn precision_i64(&self) -> i64 {
-        self.precision as i64
+    // a call as the last instruction of a frame replaces it, as dc does, so
+    // that loops run in constant memory
+    fn call(&mut self, mut frame: Frame) -> VMState {
+        if self.frames.last().is_some_and(Frame::finished) {
+            let caller = self.frames.pop().expect("a running frame");
+            frame.levels = frame.levels.saturating_add(caller.levels);
+        }
+        self.frames.push(frame);
+        VMState::Call
     }
 
     fn eval_instruction(&mut self, instruction: &Instruction) -> Result<VMState, VMError> {
         let state = match instruction {
             &Instruction::Nop => VMState::Continue,
-            &Instruction::Num(integer, fraction) => {
+            &Instruction::Num(sign, integer, fraction) => {
                 if let Err(stack_error) =
                     self.stack
-                        .push_bytes_as_num(integer, fraction, self.input_radix)
+                        .push_bytes_as_num(sign, integer, fraction, self.parameters.input_radix())
                 {
                     VMState::StackError(stack_error)
                 } else {
@@ -400,80 +664,156 @@ where
             // print
             &Instruction::PrintLN => match self.stack.peek() {
                 Ok(tos) => {
-                    writeln!(self.sink, "{}", tos.to_str_radix(self.output_radix))?;
+                    tos.write_to(&mut self.sink, self.parameters.output_radix(), self.line_length)?;
                     VMState::Continue
                 }
                 Err(stack_error) => VMState::StackError(stack_error),
             },
             &Instruction::PrintPop => match self.stack.pop() {
                 Ok(tos) => {
-                    writeln!(self.sink, "{}", tos.to_str_radix(self.output_radix))?;
+                    tos.write_to(&mut self.sink, self.parameters.output_radix(), self.line_length)?;
+                    VMState::Continue
+                }
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
+            &Instruction::PrettyPrint => match self.stack.pop() {
+                Ok(dcstack::MemoryCell::Str(bytes)) => {
+                    self.sink.write_all(&bytes)?;
+                    VMState::Continue
+                }
+                Ok(dcstack::MemoryCell::Num(n)) => {
+                    self.sink.write_all(&numeric::to_base256_bytes(&n))?;
                     VMState::Continue
                 }
                 Err(stack_error) => VMState::StackError(stack_error),
             },
-            &Instruction::PrettyPrint => VMState::NotImplemented,
             &Instruction::PrintStack => {
-                self.stack.write_to(&mut self.sink, self.output_radix)?;
+                self.stack
+                    .write_to(&mut self.sink, self.parameters.output_radix(), self.line_length)?;
                 VMState::Continue
             }
             // arithmetic
-            &Instruction::Add => bin_op![self.stack; BigDecimal::add_assign],
-            &Instruction::Sub => bin_op![self.stack; BigDecimal::sub_assign],
-            &Instruction::Mul => bin_op![self.stack; BigDecimal::mul_assign],
+            &Instruction::Add => bin_op![self.stack; |lhs: &mut BigDecimal, rhs| {
+                *lhs = numeric::add(lhs, &rhs)
+            }],
+            &Instruction::Sub => bin_op![self.stack; |lhs: &mut BigDecimal, rhs| {
+                *lhs = numeric::sub(lhs, &rhs)
+            }],
+            &Instruction::Mul => {
+                let precision = self.parameters.precision();
+                bin_op![self.stack; |lhs: &mut BigDecimal, rhs| {
+                    *lhs = numeric::mul(lhs, &rhs, precision)
+                }]
+            }
             &Instruction::Div => {
-                let precision = self.precision_i64();
-                bin_op![self.stack; |dest, other| {*dest = &*dest / other; *dest = dest.with_scale(precision)}]
+                let precision = self.parameters.precision();
+                VMState::from(self.stack.binary_try_apply_and_consume_tos(|dividend, divisor| {
+                    numeric::div(dividend, divisor, precision)
+                }))
             }
-            &Instruction::Mod => bin_op![self.stack; |dest, other| *dest = &*dest % other],
-            &Instruction::Divmod => VMState::NotImplemented,
-            &Instruction::Exp => VMState::NotImplemented,
-            &Instruction::Modexp => VMState::NotImplemented,
-            &Instruction::Sqrt => {
-                // TODO: this implementation is buggy as it goes through fp
-                let precision = self.precision_i64();
-                VMState::from(self.stack.apply_tos_num_opt(|n| {
-                    ToPrimitive::to_f64(n)
-                        .map(f64::sqrt)
-                        .and_then(BigDecimal::from_f64)
-                        .map(|n| n.with_scale(precision))
+            &Instruction::Mod => {
+                let precision = self.parameters.precision();
+                VMState::from(self.stack.binary_try_apply_and_consume_tos(|dividend, divisor| {
+                    numeric::rem(dividend, divisor, precision)
                 }))
             }
+            &Instruction::Divmod => {
+                let precision = self.parameters.precision();
+                VMState::from(
+                    self.stack
+                        .binary_try_apply_and_consume_tos_pair(|dividend, divisor| {
+                            numeric::divmod(dividend, divisor, precision)
+                        }),
+                )
+            }
+            &Instruction::Exp => {
+                let precision = self.parameters.precision();
+                let fractional_exponent = match self.stack.peek() {
+                    Ok(dcstack::MemoryCell::Num(exponent)) => {
+                        exponent.as_bigint_and_exponent().1 != 0
+                    }
+                    _ => false,
+                };
+                match self.stack.binary_try_apply_and_consume_tos(|base, exponent| {
+                    numeric::pow(base, exponent, precision)
+                }) {
+                    Ok(()) => {
+                        // dc warns, but still uses the integer part of the exponent
+                        if fractional_exponent {
+                            writeln!(self.error_sink, "dc: {}", dcstack::DCError::NonZeroScaleInExponent)?;
+                        }
+                        VMState::Continue
+                    }
+                    Err(stack_error) => VMState::StackError(stack_error),
+                }
+            }
+            &Instruction::Modexp => VMState::from(
+                self.stack
+                    .ternary_try_apply_and_consume_tos(numeric::modexp),
+            ),
+            &Instruction::Sqrt => {
+                let precision = self.parameters.precision();
+                VMState::from(self.stack.try_apply_tos_num(|n| numeric::sqrt(n, precision)))
+            }
             // stack
             &Instruction::Clear => VMState::from(self.stack.clear()),
             &Instruction::Dup => VMState::from(self.stack.dup()),
             &Instruction::Swap => VMState::from(self.stack.swap()),
             // register
-            &Instruction::RegisterOperation { .. } => VMState::NotImplemented,
-            // parameters
-            &Instruction::SetInputRadix => {
-                VMState::from(self.stack.pop_num().map(|n| self.set_input_radix(n)))
+            &Instruction::RegisterOperation(optype, register) => {
+                self.eval_register_operation(optype, register)?
             }
+            // parameters
+            &Instruction::SetInputRadix => match self.stack.pop() {
+                Ok(radix) => self.set_input_radix(&radix)?,
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
             &Instruction::GetInputRadix => {
-                self.stack.push_num(self.input_radix);
+                self.stack.push_num(self.parameters.input_radix());
                 VMState::Continue
             }
-            &Instruction::SetOutputRadix => {
-                VMState::from(self.stack.pop_num().map(|n| self.set_output_radix(n)))
-            }
+            &Instruction::SetOutputRadix => match self.stack.pop() {
+                Ok(radix) => self.set_output_radix(&radix)?,
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
             &Instruction::GetOutputRadix => {
-                self.stack.push_num(self.output_radix);
+                self.stack.push_num(self.parameters.output_radix());
                 VMState::Continue
             }
-            &Instruction::SetPrecision => {
-                VMState::from(self.stack.pop_num().map(|n| self.set_precision(n)))
-            }
+            &Instruction::SetPrecision => match self.stack.pop() {
+                Ok(precision) => self.set_precision(&precision)?,
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
             &Instruction::GetPrecision => {
-                self.stack.push_num(self.precision);
+                self.stack.push_num(self.parameters.precision());
                 VMState::Continue
             }
             // string
-            &Instruction::OpToString => VMState::NotImplemented,
+            &Instruction::OpToString => match self.stack.pop() {
+                Ok(dcstack::MemoryCell::Num(n)) => {
+                    self.stack.push_str(&[numeric::low_order_byte(&n)]);
+                    VMState::Continue
+                }
+                Ok(dcstack::MemoryCell::Str(bytes)) => {
+                    self.stack.push_str(&bytes[..cmp::min(1, bytes.len())]);
+                    VMState::Continue
+                }
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
             &Instruction::ExecuteTos => match self.stack.pop_str() {
-                Ok(bytes) => self.execute_macro(&bytes)?.into(),
+                Ok(bytes) => self.call(Frame::new(bytes, 1)),
                 Err(stack_error) => VMState::StackError(stack_error),
             },
-            &Instruction::ExecuteInput => VMState::NotImplemented,
+            // the line runs at the current macro level, so q works as if it
+            // was written in place of ?
+            &Instruction::ExecuteInput => {
+                let mut line = Vec::new();
+                match self.line_source {
+                    Some(ref mut line_source) => line_source.read_line(&mut line)?,
+                    None => self.input.read_until(b'\n', &mut line)?,
+                };
+                self.call(Frame::new(line, 0))
+            }
             &Instruction::ReturnCaller => VMState::TerminatingReturn,
             &Instruction::ReturnN => match self.stack.pop_num() {
                 Ok(levels) => {
@@ -486,66 +826,192 @@ where
                 Err(stack_error) => VMState::StackError(stack_error),
             },
             // status enquiry
-            &Instruction::Digits => VMState::NotImplemented,
-            &Instruction::FractionDigits => VMState::NotImplemented,
+            &Instruction::Digits => match self.stack.pop() {
+                Ok(dcstack::MemoryCell::Num(n)) => {
+                    self.stack.push_num(numeric::digits(&n));
+                    VMState::Continue
+                }
+                Ok(dcstack::MemoryCell::Str(bytes)) => {
+                    self.stack.push_num(bytes.len() as u64);
+                    VMState::Continue
+                }
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
+            &Instruction::FractionDigits => match self.stack.pop() {
+                Ok(dcstack::MemoryCell::Num(n)) => {
+                    self.stack.push_num(numeric::fraction_digits(&n));
+                    VMState::Continue
+                }
+                Ok(dcstack::MemoryCell::Str(..)) => {
+                    self.stack.push_num(0);
+                    VMState::Continue
+                }
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
             &Instruction::StackDepth => {
                 let len = self.stack.len();
                 self.stack.push_num(len as u64);
                 VMState::Continue
             }
             // miscellaneous
-            &Instruction::System(..) => VMState::NotImplemented,
+            &Instruction::System(command) => {
+                match self.command_runner
+                    .run(command, &mut self.sink, &mut self.error_sink)
+                {
+                    Ok(()) => VMState::Continue,
+                    Err(error) => VMState::CommandError(error.to_string()),
+                }
+            }
             &Instruction::Comment(..) => VMState::Continue,
         };
         Ok(state)
     }
 
-    fn set_input_radix(&mut self, radix: BigDecimal) -> VMState {
-        let (n, scale) = radix.as_bigint_and_exponent();
-        if scale != 0 {
-            return VMState::InvalidInputRadix;
-        }
-
-        match n.to_u32() {
-            Some(n) => {
-                if n < 2 || n > 16 {
-                    VMState::InvalidInputRadix
-                } else {
-                    self.input_radix = n;
+    fn eval_register_operation(
+        &mut self,
+        optype: RegisterOperationType,
+        register: Register,
+    ) -> Result<VMState, VMError> {
+        let state = match optype {
+            RegisterOperationType::Store => match self.stack.pop() {
+                Ok(value) => {
+                    self.registers.store(register, value);
+                    VMState::Continue
+                }
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
+            RegisterOperationType::Load => match self.registers.load(register) {
+                Ok(value) => {
+                    self.stack.push(value);
                     VMState::Continue
                 }
+                Err(register_error) => VMState::StackError(register_error),
+            },
+            RegisterOperationType::StoreStack => match self.stack.pop() {
+                Ok(value) => {
+                    self.registers.push(register, value);
+                    VMState::Continue
+                }
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
+            RegisterOperationType::LoadStack => match self.registers.pop(register) {
+                Ok(value) => {
+                    self.stack.push(value);
+                    VMState::Continue
+                }
+                Err(register_error) => VMState::StackError(register_error),
+            },
+            // like dc, both operands are consumed even when the index is invalid
+            RegisterOperationType::SetArray => match (self.stack.pop(), self.stack.pop()) {
+                (Ok(index), Ok(value)) => match array_index(&index) {
+                    Ok(index) => {
+                        self.registers.set_array(register, index, value);
+                        VMState::Continue
+                    }
+                    Err(index_error) => VMState::StackError(index_error),
+                },
+                (Err(stack_error), _) | (_, Err(stack_error)) => VMState::StackError(stack_error),
+            },
+            RegisterOperationType::GetArray => match self.stack.pop() {
+                Ok(index) => match array_index(&index) {
+                    Ok(index) => {
+                        let value = self.registers.get_array(register, index);
+                        self.stack.push(value);
+                        VMState::Continue
+                    }
+                    Err(index_error) => VMState::StackError(index_error),
+                },
+                Err(stack_error) => VMState::StackError(stack_error),
+            },
+            RegisterOperationType::TosGtExecute => {
+                self.conditional_execute(register, |tos, second| tos > second)?
             }
-            None => VMState::InvalidInputRadix,
-        }
+            RegisterOperationType::TosLtExecute => {
+                self.conditional_execute(register, |tos, second| tos < second)?
+            }
+            RegisterOperationType::TosEqExecute => {
+                self.conditional_execute(register, |tos, second| tos == second)?
+            }
+            RegisterOperationType::TosNeExecute => {
+                self.conditional_execute(register, |tos, second| tos != second)?
+            }
+            RegisterOperationType::TosGeExecute => {
+                self.conditional_execute(register, |tos, second| tos >= second)?
+            }
+            RegisterOperationType::TosLeExecute => {
+                self.conditional_execute(register, |tos, second| tos <= second)?
+            }
+        };
+        Ok(state)
     }
 
-    fn set_output_radix(&mut self, radix: BigDecimal) -> VMState {
-        let (n, scale) = radix.as_bigint_and_exponent();
-        if scale != 0 {
-            return VMState::InvalidOutputRadix;
+    fn conditional_execute<F>(
+        &mut self,
+        register: Register,
+        condition: F,
+    ) -> Result<VMState, VMError>
+    where
+        F: Fn(&BigDecimal, &BigDecimal) -> bool,
+    {
+        let (tos, second) = match self.stack.pop_num_pair() {
+            Ok(pair) => pair,
+            Err(stack_error) => return Ok(VMState::StackError(stack_error)),
+        };
+        if !condition(&tos, &second) {
+            return Ok(VMState::Continue);
         }
+        let state = match self.registers.load(register) {
+            Ok(dcstack::MemoryCell::Str(bytes)) => self.call(Frame::new(bytes, 1)),
+            // dc just pushes numbers back, as it does when executing them
+            Ok(number) => {
+                self.stack.push(number);
+                VMState::Continue
+            }
+            Err(register_error) => VMState::StackError(register_error),
+        };
+        Ok(state)
+    }
 
-        match n.to_u32() {
-            Some(n) => {
-                if n < 2 || n > 16 {
-                    VMState::InvalidOutputRadix
-                } else {
-                    self.output_radix = n;
-                    VMState::Continue
+    // dc truncates the operands of `i`, `o` and `k`, warning when they do
+    // not fit; strings are always invalid
+    fn parameter_value(
+        &mut self,
+        value: &dcstack::MemoryCell,
+        invalid: i64,
+    ) -> Result<i64, VMError> {
+        match *value {
+            dcstack::MemoryCell::Num(ref n) => match numeric::to_simple_integer(n) {
+                Some(n) => Ok(n),
+                None => {
+                    writeln!(self.error_sink, "dc: value overflows simple integer; punting...")?;
+                    Ok(-1)
                 }
-            }
-            None => VMState::InvalidOutputRadix,
+            },
+            dcstack::MemoryCell::Str(..) => Ok(invalid),
         }
     }
 
-    fn set_precision(&mut self, precision: BigDecimal) -> VMState {
-        if let Some(value) = precision.to_u64() {
-            if value >= 2 {
-                self.precision = value;
-                return VMState::Continue;
-            }
-        }
-        VMState::InvalidPrecision
+    fn set_input_radix(&mut self, radix: &dcstack::MemoryCell) -> Result<VMState, VMError> {
+        let radix = self.parameter_value(radix, 0)?;
+        Ok(VMState::from(self.parameters.set_input_radix(radix)))
+    }
+
+    fn set_output_radix(&mut self, radix: &dcstack::MemoryCell) -> Result<VMState, VMError> {
+        let radix = self.parameter_value(radix, 0)?;
+        Ok(VMState::from(self.parameters.set_output_radix(radix)))
+    }
+
+    fn set_precision(&mut self, precision: &dcstack::MemoryCell) -> Result<VMState, VMError> {
+        let precision = self.parameter_value(precision, -1)?;
+        Ok(VMState::from(self.parameters.set_precision(precision)))
+    }
+}
+
+// dc truncates fractional indices
+fn array_index(index: &dcstack::MemoryCell) -> Result<u64, dcstack::DCError> {
+    match *index {
+        dcstack::MemoryCell::Num(ref n) => n.to_u64().ok_or(dcstack::DCError::InvalidArrayIndex),
+        dcstack::MemoryCell::Str(..) => Err(dcstack::DCError::InvalidArrayIndex),
     }
 }
 
@@ -584,36 +1050,42 @@ macro_rules! test_vm {
 }
 
 #[cfg(test)]
-type InMemoryVM = VM<Vec<u8>, Vec<u8>>;
+type InMemoryVM = VM<io::Cursor<Vec<u8>>, Vec<u8>, Vec<u8>>;
 
 #[test]
 fn test_input_radix() {
     let mut vm = InMemoryVM::default();
-    assert!(vm.set_input_radix(BigDecimal::from(10)).is_ok());
+    assert!(vm.set_input_radix(&dcstack::MemoryCell::from(10)).unwrap().is_ok());
 }
 
 #[test]
 fn test_input_radix_fail() {
     let mut vm = InMemoryVM::default();
-    assert!(vm.set_input_radix(BigDecimal::from(50)).is_err());
+    assert!(vm.set_input_radix(&dcstack::MemoryCell::from(50)).unwrap().is_err());
 }
 
 #[test]
 fn test_output_radix() {
     let mut vm = InMemoryVM::default();
-    assert!(vm.set_output_radix(BigDecimal::from(10)).is_ok());
+    assert!(vm.set_output_radix(&dcstack::MemoryCell::from(10)).unwrap().is_ok());
 }
 
 #[test]
 fn test_output_radix_fail() {
     let mut vm = InMemoryVM::default();
-    assert!(vm.set_output_radix(BigDecimal::from(1)).is_err());
+    assert!(vm.set_output_radix(&dcstack::MemoryCell::from(1)).unwrap().is_err());
+}
+
+#[test]
+fn test_output_radix_big() {
+    let mut vm = InMemoryVM::default();
+    assert!(vm.set_output_radix(&dcstack::MemoryCell::from(1000)).unwrap().is_ok());
 }
 
 #[test]
 fn test_precision() {
     let mut vm = InMemoryVM::default();
-    assert!(vm.set_precision(BigDecimal::from(10)).is_ok());
+    assert!(vm.set_precision(&dcstack::MemoryCell::from(10)).unwrap().is_ok());
 }
 
 #[test]
@@ -667,7 +1139,7 @@ fn test_add_other_not_num() {
 
 #[test]
 fn test_exec() {
-    let mut vm = VM::default();
+    let mut vm = InMemoryVM::default();
     assert!(vm.execute(b"110.0[p]x").is_ok());
     let (actual_output, actual_error) = vm.sinks();
 
@@ -685,7 +1157,7 @@ macro_rules! test_exec {
         #[test]
         fn $name() {
             let program = $program;
-            let mut vm = VM::default();
+            let mut vm = InMemoryVM::default();
             match parse::parse(program) {
                 Err(error) => {
                     println!("parse error {:?}", error);
@@ -708,32 +1180,87 @@ macro_rules! test_exec {
     };
 }
 
+macro_rules! test_exec_error {
+    ($name:ident; $program:expr; $expected_output:expr; $expected_error:expr) => {
+        #[test]
+        fn $name() {
+            let mut vm = InMemoryVM::default();
+            assert!(vm.execute($program).is_ok());
+            let (actual_output, actual_error) = vm.sinks();
+
+            assert_eq!(
+                (String::from($expected_output), String::from($expected_error)),
+                (
+                    String::from_utf8(actual_output).expect("utf8 output"),
+                    String::from_utf8(actual_error).expect("utf8 error")
+                ),
+            );
+        }
+    };
+}
+
 test_exec![test_num;b"10";""];
 test_exec![test_p;b"10p";"10\n"];
 test_exec![test_p2;b"10n";"10\n"];
 test_exec![test_p2p;b"10nzp";"10\n0\n"];
 
+test_exec![test_negative_literal;b"_5p _1.25p _5 3+p";"-5\n-1.25\n-2\n"];
+test_exec![test_negative_literal_hex;b"16i _FFp _A.8p";"-255\n-10.5\n"];
+test_exec![test_negative_literal_binary;b"2i _101p";"-5\n"];
+test_exec![test_negative_literals_adjacent;b"1_2f";"-2\n1\n"];
+test_exec![test_print_fraction;b".5p _.5p 0.050p";".5\n-.5\n.050\n"];
+test_exec![test_print_trailing_zeros;b"1.500p 2.0 3*p";"1.500\n6.0\n"];
+test_exec![test_print_zero_with_scale;b"0.000p 1.5 1.5-p";"0\n0\n"];
+test_exec![test_print_stack_fractions;b".5 _.25f";"-.25\n.5\n"];
+test_exec![test_print_no_exponent;b"10 30^p 40k 1 10 30^/p";"1000000000000000000000000000000\n.0000000000000000000000000000010000000000\n"];
+test_exec![test_add_scale;b"1 .50+p 1.5 1.500-p";"1.50\n0\n"];
+test_exec![test_mul_scale;b".5 .5*p 1.05 1.05*p 5k .5 .5*p 1.05 1.05*p";".2\n1.10\n.25\n1.1025\n"];
+test_exec![test_mul_scale_precision;b"2k 1.234 3.332*p 2 3*p";"4.111\n6\n"];
+test_exec![test_div_truncates;b"2k 2 3/p _2 3/p 1.999 1/p";".66\n-.66\n1.99\n"];
+test_exec![test_mod_scale;b"5 2.5%p _7 2%p 2k 5.55 2%p";"0\n-1\n.01\n"];
+test_exec![test_precision_zero_and_one;b"1k K 2 3/ 0k K 2 3/f";"0\n0\n.6\n1\n"];
+test_exec![test_precision_truncated;b"2.9k Kp 16.5i Ip 8.2o Op";"2\n16\n10\n"];
+test_exec_error![test_precision_negative;b"_1k Kp";"0\n";"dc: scale must be a nonnegative number\n"];
+test_exec_error![test_precision_string;b"[2]k Kp";"0\n";"dc: scale must be a nonnegative number\n"];
+test_exec_error![test_precision_overflow;b".5k 100000000000000000000k Kp";"0\n";"dc: value overflows simple integer; punting...\ndc: scale must be a nonnegative number\ndc: value overflows simple integer; punting...\ndc: scale must be a nonnegative number\n"];
+test_exec_error![test_input_radix_invalid;b"1i 17i _10i [a]i Ip";"10\n";"dc: input base must be a number between 2 and 16 (inclusive)\ndc: input base must be a number between 2 and 16 (inclusive)\ndc: input base must be a number between 2 and 16 (inclusive)\ndc: input base must be a number between 2 and 16 (inclusive)\n"];
+test_exec_error![test_output_radix_invalid;b"1o 1.9o [a]o Op";"10\n";"dc: output base must be a number greater than 1\ndc: output base must be a number greater than 1\ndc: output base must be a number greater than 1\n"];
+test_exec_error![test_parameter_stack_empty;b"k";"";"dc: stack empty\n"];
+test_exec![test_nested_string;b"[[inner]p]p";"[inner]p\n"];
+test_exec![test_nested_macro;b"[[inner]p]x [[42p]sa]x lax";"inner\n42\n"];
 test_exec![test_oct;b"8o 8p";"10\n"];
+test_exec![test_hex_fraction;b"16o .5p 255.75p";".8\nFF.C0\n"];
+test_exec![test_hex_negative;b"16o 0 255.5-p";"-FF.8\n"];
+test_exec![test_binary_fraction;b"2o 1.1p";"1.0001\n"];
+test_exec![test_output_base_60;b"60o 3661p";" 01 01 01\n"];
+test_exec![test_output_base_1000;b"1000o 1234567p";" 001 234 567\n"];
+test_exec![test_output_base_256;b"256o 65535p 0p";" 255 255\n0\n"];
+test_exec![test_output_base_big_fraction;b"60o 1.5p";" 01.30\n"];
+test_exec![test_output_base_big_stack;b"17o 16 17f";" 01 00\n 16\n"];
 
-// this does not fail because it does not parse non decimal
 test_exec![test_input_set_get_base;b"8iIp";"8\n"];
-#[cfg(feature = "parse_all_bases")]
 test_exec![test_input_hex;b"16iAp";"10\n"];
-#[cfg(feature = "parse_all_bases")]
 test_exec![test_input_hex_aa;b"16iAAp";"170\n"];
-#[cfg(feature = "parse_all_bases")]
 test_exec![test_input_hex_dec;b"16iA.Ap";"10.6\n"];
-#[cfg(feature = "parse_all_bases")]
 test_exec![test_input_bin_dec;b"2i1.101p";"1.625\n"];
-// test_exec![test_input_oct;b"8i 10p";"8\n"];
+test_exec![test_input_oct;b"8i 10p";"8\n"];
+test_exec![test_input_single_digit;b"2i Ap 8i Fp";"10\n15\n"];
+test_exec![test_input_digit_beyond_radix;b"2i 12p Ai 1Fp";"4\n25\n"];
+test_exec![test_input_back_to_decimal;b"16i A i 99p";"99\n"];
 
 // sqrt
-// test_exec![test_sqrt;b".4vp";"0.6\n"];
-// test_exec![test_sqrt_with_precision;b"2p.4vp";"0.63\n"];
+test_exec![test_sqrt;b".4vp";".6\n"];
+test_exec![test_sqrt_with_precision;b"2k.4vp";".63\n"];
 test_exec![test_sqrt_on_int;b"4vp";"2\n"];
 test_exec![test_sqrt_on_int_with_precision;b"2k4vp";"2.00\n"];
 test_exec![test_sqrt_on_int_irr_resul;b"2vp";"1\n"];
 test_exec![test_sqrt_on_int_with_precision_irr_resul;b"2k2vp";"1.41\n"];
+test_exec![test_sqrt_zero;b"0vp";"0\n"];
+test_exec![test_sqrt_keeps_scale;b"2.0000vp";"1.4142\n"];
+test_exec![test_sqrt_beyond_f64;b"10 400^vp";"100000000000000000000000000000000000000000000000000000000000000000000\\\n000000000000000000000000000000000000000000000000000000000000000000000\\\n000000000000000000000000000000000000000000000000000000000000000\n"];
+test_exec![test_sqrt_many_digits;b"40k 2vp";"1.4142135623730950488016887242096980785696\n"];
+test_exec_error![test_sqrt_negative;b"0 4-vp";"-4\n";"dc: square root of negative number\n"];
+test_exec_error![test_sqrt_string;b"[a]vzp";"1\n";"dc: non-numeric value\n"];
 
 test_exec![_10p; b"10p";"10\n"];
 test_exec![add; b"10 20 + p";"30\n"];
@@ -752,3 +1279,376 @@ test_exec![quit_now1;b"q"; ""];
 test_exec![quit;b"[qp]x10p"; ""];
 test_exec![quit2;b"[qp]x"; ""];
 test_exec![no_quit_macro_depth;b"[qp][x]x10p";"10\n"];
+test_exec![quit_tail_call;b"[q]sa [10p lax]x 20p";"10\n20\n"];
+test_exec![quit_tail_call_blanks;b"[q]sa [10p lax \n]x 20p";"10\n20\n"];
+test_exec![quit_levels;b"[[1Q 1p]x 2p]x 3p";"3\n"];
+test_exec![quit_levels_tail_call;b"[[1Q 1p]x]x 3p";"3\n"];
+test_exec![tail_call_loop;b"0[1+d1000000>a]dsax p";"1000000\n"];
+test_exec![tail_execute_loop;b"[q]sq 1000000[1-d0=q lax]dsax p";"0\n"];
+test_exec![deep_recursion;b"[1-d0<a 1+]sa 100000 lax p";"100000\n"];
+
+// a frame resumed after each call goes on where it stopped
+#[cfg(test)]
+fn many_calls(calls: usize) -> Vec<u8> {
+    let mut program = b"0[1+]sa ".to_vec();
+    for _ in 0..calls {
+        program.extend_from_slice(b"lax ");
+    }
+    program.extend_from_slice(b"p");
+    program
+}
+
+test_exec![many_calls_in_frame;&many_calls(100000)[..];"100000\n"];
+test_exec![many_calls_in_macro;&[b"[", &many_calls(100000)[..], b"]x"].concat()[..];"100000\n"];
+
+// registers
+test_exec![store_load;b"10sa lap lap";"10\n10\n"];
+test_exec![store_replaces_top;b"1Sa 2sa lap Lap";"2\n2\n"];
+test_exec![store_string;b"[hello]sa lap";"hello\n"];
+test_exec![stack_registers;b"1Sa 2Sa 3Sa LaLaLa f";"1\n2\n3\n"];
+test_exec![stack_register_load_does_not_pop;b"1Sa 2Sa la La La f";"1\n2\n2\n"];
+test_exec_error![load_empty_register;b"la 10p";"10\n";"dc: register 'a' (0141) is empty\n"];
+test_exec_error![pop_empty_register;b"1sa La La 10p";"10\n";"dc: stack register 'a' (0141) is empty\n"];
+test_exec_error![store_empty_stack;b"sa la";"";"dc: stack empty\ndc: register 'a' (0141) is empty\n"];
+
+// arrays
+test_exec![array_set_get;b"10 0:a 20 1:a 0;ap 1;ap";"10\n20\n"];
+test_exec![array_unset_is_zero;b"5;ap";"0\n"];
+test_exec![array_sparse;b"[big]123456789012:a 123456789012;ap 1000;ap";"big\n0\n"];
+test_exec![array_truncated_index;b"7 2.9:a 2;ap";"7\n"];
+test_exec![array_per_register_level;b"1 0:a 0Sa 2 0:a 0;ap La 0;ap";"2\n1\n"];
+test_exec![array_survives_store;b"1 0:a 5sa 0;ap";"1\n"];
+test_exec_error![array_negative_index;b"1 0 1-:a 10p";"10\n";"dc: array index must be a nonnegative integer\n"];
+test_exec_error![array_string_index;b"[x];a zp";"0\n";"dc: array index must be a nonnegative integer\n"];
+
+// conditionals
+test_exec![gt_true;b"[9p]sa 1 2>a";"9\n"];
+test_exec![gt_false;b"[9p]sa 2 1>a";""];
+test_exec![lt_true;b"[9p]sa 2 1<a";"9\n"];
+test_exec![lt_false;b"[9p]sa 1 2<a 1 1<a";""];
+test_exec![eq_true;b"[9p]sa 1 1.0=a";"9\n"];
+test_exec![eq_false;b"[9p]sa 1 2=a zp";"0\n"];
+test_exec![ne_true;b"[9p]sa 1 2!=a";"9\n"];
+test_exec![ne_false;b"[9p]sa 2 2!=a";""];
+test_exec![ge;b"[9p]sa 2 1!<a 1 1!<a 1 2!<a";"9\n9\n"];
+test_exec![le;b"[9p]sa 1 2!>a 1 1!>a 2 1!>a";"9\n9\n"];
+test_exec![conditional_loop;b"[p1+d5>a]sa 1 lax";"1\n2\n3\n4\n"];
+test_exec![conditional_quit;b"[q]sa [1 1=a 10p]x 20p";"20\n"];
+test_exec![conditional_number_register;b"5sa 1 1=a p";"5\n"];
+test_exec_error![conditional_empty_stack;b"[9p]sa 1>a zp";"1\n";"dc: stack empty\n"];
+test_exec_error![conditional_non_numeric;b"[9p]sa 1[x]>a zp";"0\n";"dc: non-numeric value\n"];
+test_exec_error![conditional_empty_register;b"1 1=a zp";"0\n";"dc: register 'a' (0141) is empty\n"];
+
+// exponentiation
+test_exec![exp;b"2 10^p";"1024\n"];
+test_exec![exp_zero;b"0 0^p";"1\n"];
+test_exec![exp_negative_base;b"0 2- 3^p";"-8\n"];
+test_exec![exp_scale;b"1.5 3^p";"3.3\n"];
+test_exec![exp_scale_with_precision;b"10k 1.5 3^p";"3.375\n"];
+test_exec![exp_negative;b"2k .5 0 1-^p";"2.00\n"];
+test_exec![exp_big;b"2 100^p";"1267650600228229401496703205376\n"];
+test_exec_error![exp_fractional;b"2 3.9^p";"8\n";"dc: non-zero scale in exponent\n"];
+test_exec_error![exp_too_large;b"2 100000000000000000000^zp";"2\n";"dc: exponent too large in raise\n"];
+test_exec_error![exp_negative_zero;b"0 0 1-^zp";"2\n";"dc: divide by zero\n"];
+test_exec_error![exp_non_numeric;b"2[a]^zp";"2\n";"dc: non-numeric value\n"];
+
+// modular exponentiation
+test_exec![modexp;b"4 13 497|p";"445\n"];
+test_exec![modexp_zero_exponent;b"4 0 497|p 4 0 1|p";"1\n0\n"];
+test_exec![modexp_negative_base;b"0 4- 3 5|p";"-4\n"];
+test_exec![modexp_huge;b"2 100000000000000000000000000000 1000000007|p";"931466512\n"];
+test_exec_error![modexp_zero_modulus;b"4 13 0|zp";"3\n";"dc: remainder by zero\n"];
+test_exec_error![modexp_negative_exponent;b"4 0 1- 5|zp";"3\n";"dc: negative exponent\n"];
+test_exec_error![modexp_fractional;b"4.5 2 5|zp";"3\n";"dc: non-zero scale in base\n"];
+test_exec_error![modexp_stack_empty;b"2 5|zp";"2\n";"dc: stack empty\n"];
+
+// division
+test_exec![div_precision;b"2k 10 3/p";"3.33\n"];
+test_exec![mod_precision;b"2k 10 3%p";".01\n"];
+test_exec![divmod;b"10 3~f";"1\n3\n"];
+test_exec![divmod_precision;b"2k 10 3~f";".01\n3.33\n"];
+test_exec![divmod_negative;b"0 7- 2~f";"-1\n-3\n"];
+test_exec_error![div_by_zero;b"10 0/f";"0\n10\n";"dc: divide by zero\n"];
+test_exec_error![mod_by_zero;b"10 0%f";"0\n10\n";"dc: remainder by zero\n"];
+test_exec_error![divmod_by_zero;b"10 0~f";"0\n10\n";"dc: divide by zero\n"];
+test_exec_error![divmod_stack_empty;b"10~f";"10\n";"dc: stack empty\n"];
+
+// pretty print
+test_exec![pretty_print_str;b"[hello]P [ world]P zp";"hello world0\n"];
+test_exec![pretty_print_num;b"4276803P 10P";"ABC\n"];
+test_exec![pretty_print_zero;b"0P 1zp";"1\n"];
+test_exec_error![pretty_print_empty;b"P";"";"dc: stack empty\n"];
+
+#[test]
+fn test_pretty_print_binary() {
+    let mut vm = InMemoryVM::default();
+    assert!(vm.execute(b"255P 65534P 27P").is_ok());
+    let (actual_output, actual_error) = vm.sinks();
+    assert_eq!(vec![0xff, 0xff, 0xfe, 0x1b], actual_output);
+    assert!(actual_error.is_empty());
+}
+
+// to string
+test_exec![to_string_num;b"65ap 321ap 65.9ap";"A\nA\nA\n"];
+test_exec![to_string_str;b"[hello]ap []azp";"h\n2\n"];
+test_exec![to_string_macro;b"3 4 42ax p";"12\n"];
+test_exec_error![to_string_empty;b"a";"";"dc: stack empty\n"];
+
+#[test]
+fn test_to_string_print_binary() {
+    let mut vm = InMemoryVM::default();
+    assert!(vm.execute(b"255ap").is_ok());
+    let (actual_output, actual_error) = vm.sinks();
+    assert_eq!(vec![0xff, b'\n'], actual_output);
+    assert!(actual_error.is_empty());
+}
+
+#[test]
+fn test_to_string_print_stack_binary() {
+    let mut vm = InMemoryVM::default();
+    assert!(vm.execute(b"200a f").is_ok());
+    let (actual_output, actual_error) = vm.sinks();
+    assert_eq!(vec![0xc8, b'\n'], actual_output);
+    assert!(actual_error.is_empty());
+}
+
+// line length
+test_exec![test_line_wrapping;b"2 300^p";"203703597633448608626844568840937816105146839366593625063614044935438\\\n1299763336706183397376\n"];
+test_exec![test_line_wrapping_stack;b"2 300^ 1f";"1\n203703597633448608626844568840937816105146839366593625063614044935438\\\n1299763336706183397376\n"];
+
+#[test]
+fn test_line_length() {
+    let mut vm = InMemoryVM::default();
+    vm.set_line_length(4);
+    assert!(vm.execute(b"1234567p [1234567]p 0 1234567-n").is_ok());
+    let (output, _) = vm.sinks();
+    assert_eq!(
+        "123\\\n456\\\n7\n1234567\n-12\\\n345\\\n67\n",
+        String::from_utf8(output).unwrap()
+    );
+}
+
+#[test]
+fn test_line_length_disabled() {
+    let mut vm = InMemoryVM::default();
+    vm.set_line_length(0);
+    assert!(vm.execute(b"2 300^p").is_ok());
+    let (output, _) = vm.sinks();
+    assert_eq!(
+        concat!(
+            "2037035976334486086268445688409378161051468393665936250636140449354381",
+            "299763336706183397376\n"
+        ),
+        String::from_utf8(output).unwrap()
+    );
+}
+
+#[test]
+fn test_parse_line_length() {
+    assert_eq!(0, parse_line_length("0"));
+    assert_eq!(0, parse_line_length("1"));
+    assert_eq!(100, parse_line_length("100"));
+    assert_eq!(80, parse_line_length(" 80"));
+    assert_eq!(80, parse_line_length("+80columns"));
+    assert_eq!(0, parse_line_length("wide"));
+    assert_eq!(0, parse_line_length(""));
+    assert_eq!(0, parse_line_length("-80"));
+    assert_eq!(usize::MAX, parse_line_length("99999999999999999999999"));
+}
+
+// errors
+#[test]
+fn test_errors_are_counted() {
+    let mut vm = InMemoryVM::default();
+    assert!(vm.execute(b"+ 1p [a]1+ ]").is_ok());
+    assert_eq!(3, vm.errors());
+}
+
+#[test]
+fn test_strict() {
+    let mut vm = InMemoryVM::default();
+    vm.set_strict(true);
+    assert_eq!(ReturnState::Aborted, vm.execute(b"1p [[+ 2p]x 3p]x 4p").unwrap());
+    assert_eq!(1, vm.errors());
+    let (output, error) = vm.sinks();
+    assert_eq!("1\n", String::from_utf8(output).unwrap());
+    assert_eq!("dc: stack empty\n", String::from_utf8(error).unwrap());
+}
+
+// interactive session
+#[test]
+fn test_stack_inspection() {
+    let mut vm = InMemoryVM::default();
+    assert_eq!(0, vm.stack_depth());
+    assert_eq!(None, vm.top_of_stack());
+    assert!(vm.execute(b"[a] 16o 255").is_ok());
+    assert_eq!(2, vm.stack_depth());
+    assert_eq!(Some("FF".to_string()), vm.top_of_stack());
+}
+
+#[cfg(test)]
+struct Lines(Vec<&'static str>);
+
+#[cfg(test)]
+impl LineSource for Lines {
+    fn read_line(&mut self, line: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.is_empty() {
+            return Ok(0);
+        }
+        let next = self.0.remove(0);
+        line.extend_from_slice(next.as_bytes());
+        Ok(next.len())
+    }
+}
+
+#[test]
+fn test_line_source() {
+    let mut vm = VM::new(io::Cursor::new(b"3\n".to_vec()), Vec::new(), Vec::new());
+    vm.set_line_source(Some(Box::new(Lines(vec!["1\n", "2\n"]))));
+    assert!(vm.execute(b"??+p ?z p").is_ok());
+    vm.set_line_source(None);
+    assert!(vm.execute(b"?p").is_ok());
+    let (output, _) = vm.sinks();
+    assert_eq!("3\n1\n3\n", String::from_utf8(output).unwrap());
+}
+
+// input
+macro_rules! test_exec_input {
+    ($name:ident; $program:expr; $input:expr; $expected_output:expr) => {
+        #[test]
+        fn $name() {
+            let mut vm = VM::new(io::Cursor::new($input.to_vec()), Vec::new(), Vec::new());
+            assert!(vm.execute($program).is_ok());
+            let (actual_output, actual_error) = vm.sinks();
+
+            assert_eq!(
+                (String::from($expected_output), String::new()),
+                (
+                    String::from_utf8(actual_output).expect("utf8 output"),
+                    String::from_utf8(actual_error).expect("utf8 error")
+                ),
+            );
+        }
+    };
+}
+
+test_exec_input![input;b"? p";b"10 20+\n";"30\n"];
+test_exec_input![input_one_line;b"? ? f";b"1\n2\n3\n";"2\n1\n"];
+test_exec_input![input_eof;b"? zp";b"";"0\n"];
+test_exec_input![input_no_newline;b"?p";b"5";"5\n"];
+test_exec_input![input_quit;b"[?10p]sa [lax 20p]x 30p";b"q\n";"30\n"];
+test_exec_input![input_in_macro;b"[? 1+p]sa lax lax";b"1\n2\n";"2\n3\n"];
+
+#[cfg(test)]
+fn test_execute_input(input: &[u8]) -> (ReturnState, String, String) {
+    let mut vm = VM::new(io::Cursor::new(input.to_vec()), Vec::new(), Vec::new());
+    let state = vm.execute_input().unwrap();
+    let (output, error) = vm.sinks();
+    (
+        state,
+        String::from_utf8(output).unwrap(),
+        String::from_utf8(error).unwrap(),
+    )
+}
+
+#[test]
+fn test_execute_input_lines() {
+    // ? reads the line after the one running
+    assert_eq!(
+        (ReturnState::Done, "5\n".to_string(), String::new()),
+        test_execute_input(b"?p\n5\n")
+    );
+    assert_eq!(
+        (ReturnState::Done, "1\n2\n3\n".to_string(), String::new()),
+        test_execute_input(b"[1p\n[2p\n]x]x\n3p")
+    );
+    assert_eq!(
+        (
+            ReturnState::Done,
+            "1\n2\n".to_string(),
+            "dc: ']' (0135) unimplemented\n".to_string()
+        ),
+        test_execute_input(b"1p ]\n2p\n")
+    );
+}
+
+#[test]
+fn test_execute_input_quit() {
+    assert_eq!(
+        (
+            ReturnState::TerminatingReturn,
+            "1\n".to_string(),
+            String::new()
+        ),
+        test_execute_input(b"1p q\n2p\n")
+    );
+    assert_eq!(
+        (
+            ReturnState::TerminatingReturnEnclosing,
+            String::new(),
+            String::new()
+        ),
+        test_execute_input(b"[q]x\n2p\n")
+    );
+}
+
+// system
+test_exec![system;b"!echo hello\n10p";"hello\n10\n"];
+test_exec![system_in_macro;b"[!echo 1\n]x";"1\n"];
+test_exec_error![system_stderr;b"!echo err >&2\n";"";"err\n"];
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct RecordingCommandRunner {
+    commands: ::std::rc::Rc<::std::cell::RefCell<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl system::CommandRunner for RecordingCommandRunner {
+    fn run(
+        &mut self,
+        command: &[u8],
+        stdout: &mut dyn Write,
+        _stderr: &mut dyn Write,
+    ) -> io::Result<()> {
+        let command = String::from_utf8_lossy(command).into_owned();
+        writeln!(stdout, "ran {}", command)?;
+        self.commands.borrow_mut().push(command);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_command_runner() {
+    let runner = RecordingCommandRunner::default();
+    let commands = runner.commands.clone();
+    let mut vm = InMemoryVM::default();
+    vm.set_command_runner(runner);
+    assert!(vm.execute(b"!date\n!hostname -s\n").is_ok());
+    let (actual_output, actual_error) = vm.sinks();
+    assert_eq!(vec!["date", "hostname -s"], *commands.borrow());
+    assert_eq!(b"ran date\nran hostname -s\n".to_vec(), actual_output);
+    assert!(actual_error.is_empty());
+}
+
+#[test]
+fn test_disabled_command_runner() {
+    let mut vm = InMemoryVM::default();
+    vm.set_command_runner(system::DisabledCommandRunner);
+    assert!(vm.execute(b"!date\n10p").is_ok());
+    let (actual_output, actual_error) = vm.sinks();
+    assert_eq!(b"10\n".to_vec(), actual_output);
+    assert_eq!(
+        "dc: command error: running commands is disabled\n",
+        String::from_utf8(actual_error).expect("utf8 error")
+    );
+}
+
+// status enquiry
+test_exec![digits;b"12345Zp 1.50Zp .001Zp 0Zp";"5\n3\n1\n1\n"];
+test_exec![digits_power_of_ten;b"10 20^1-Zp 10 20^Zp";"20\n21\n"];
+test_exec![digits_str;b"[hello]Zp []Zp";"5\n0\n"];
+test_exec![fraction_digits;b"12345Xp 1.50Xp .001Xp [hello]Xp";"0\n2\n3\n0\n"];
+test_exec![fraction_digits_after_div;b"5k 1 3/Xp";"5\n"];
+test_exec_error![digits_empty;b"Z";"";"dc: stack empty\n"];
+test_exec_error![fraction_digits_empty;b"X";"";"dc: stack empty\n"];
@@ -49,7 +49,7 @@ macro_rules! test_dc {
             let programs = vec![$program];
             let expected = run_dc(programs.clone()).expect("process error");
             let dc_args = prepare_arguments(programs);
-            let (actual_output, actual_error) = rdc::dc(dc_args.into_iter(), stderr, stdout);
+            let (actual_output, actual_error) = rdc::dc(dc_args.into_iter(), io::empty(), stderr, stdout);
             assert_eq!(
                 (
                     String::from_utf8(expected.stdout).expect("utf error in system dc output"),
@@ -71,7 +71,7 @@ fn test() {
     let programs = vec!["10p"];
     let expected = run_dc(programs.clone()).expect("process error");
     let dc_args = prepare_arguments(programs);
-    let (actual_output, actual_error) = rdc::dc(dc_args.into_iter(), stderr, stdout);
+    let (actual_output, actual_error) = rdc::dc(dc_args.into_iter(), io::empty(), stderr, stdout);
     assert_eq!(
         (
             String::from_utf8(expected.stdout).expect("utf error in system dc output"),
@@ -92,7 +92,7 @@ fn test_huge_Q() {
     let programs = vec!["371946139746397463926439726439764969639436932476233984734843946937638974648736487643827 Q 10p"];
     let expected = run_dc(programs.clone()).expect("process error");
     let dc_args = prepare_arguments(programs);
-    let (actual_output, actual_error) = rdc::dc(dc_args.into_iter(), stderr, stdout);
+    let (actual_output, actual_error) = rdc::dc(dc_args.into_iter(), io::empty(), stderr, stdout);
     assert_eq!(
             String::from_utf8(expected.stdout).expect("utf error in system dc output"),
             String::from_utf8(actual_output).expect("utf8 output"),
@@ -125,4 +125,38 @@ test_dc![quit;"[qp]x10p"];
 test_dc![no_quit_macro_depth;"[qp][x]x10p"];
 test_dc![Quit;"[Qp]x10p"];
 test_dc![no_Quit_macro_depth;"[Qp][x]x10p"];
-test_dc![Quit_inconsistency;"1Q10p"];
\ No newline at end of file
+test_dc![Quit_inconsistency;"1Q10p"];
+test_dc![registers;"10sa 20Sa lap Lap lap"];
+test_dc![register_empty;"la Lb"];
+test_dc![arrays;"10 0:a 20 1:a 0;ap 1;ap 2;ap"];
+test_dc![conditional_loop;"[p1+d5>a]sa 1 lax"];
+test_dc![conditional_negated;"[9p]sa 1 2!>a 2 1!<a 1 1!=a"];
+test_dc![exp;"2 10^p 1.5 3^p 10k 1.5 3^p 5k 2 0 3-^p"];
+test_dc![exp_fractional;"2 3.9^p"];
+test_dc![modexp;"4 13 497|p 2 100000000000000000000000000000 1000000007|p"];
+test_dc![modexp_errors;"4 13 0|f 4 0 1- 5|f"];
+test_dc![divmod;"10 3~f 2k 10 3~f 0 7- 2~f"];
+test_dc![division_by_zero;"10 0/ 10 0% 10 0~f"];
+test_dc![sqrt;".4vp 2k.4vp 2.0000vp 40k 2vp 10 400^vp"];
+test_dc![sqrt_negative;"0 4-vp"];
+test_dc![pretty_print;"[hello]P 4276803P 10P 0P"];
+test_dc![to_string;"65ap 321ap [hello]ap 3 4 42axp"];
+test_dc![system;"!echo hello\n10p"];
+test_dc![digits;"12345Zp 1.50Zp .001Zp 0Zp [hello]Zp 12345Xp 1.50Xp .001Xp [hello]Xp"];
+test_dc![input_radix;"16iAAp A.Ap 2i1.101p Ap"];
+test_dc![negative_literals;"_5p _1.25p _.25 1+p 16i _FFp _A.8p 1_2f"];
+test_dc![number_formatting;".5p _.5p 0.050p 1.500p 2.0 3*p 0.000p 1.5 1.5-p 10 30^p 40k 1 10 30^/p .5 _.25f"];
+test_dc![add_sub_scale;"1 .50+p 1.5 1.500-p 0.1 0.20+p _1.25 .5-p"];
+test_dc![mul_scale;".5 .5*p 1.05 1.05*p 5k .5 .5*p 1.05 1.05*p 2k 1.234 3.332*p 2 3*p"];
+test_dc![div_scale;"2 3/p 2k 2 3/p _2 3/p 1.999 1/p 10k 1 7/p"];
+test_dc![mod_scale;"5 2.5%p _7 2%p 2k 5.55 2%p 7 _3%p 4k 10.5 .3%p"];
+test_dc![interest;"2k 1000.00 1.035* 1.035* 1.035*p 1000.00 1.035 3^*p 12 /p"];
+test_dc![precision_zero_and_one;"1k K 2 3/ 0k K 2 3/f"];
+test_dc![parameters_truncated;"2.9k Kp 16.5i Ip 8.2o Op"];
+test_dc![precision_invalid;"_1k [2]k .5k 100000000000000000000k Kp"];
+test_dc![input_radix_invalid;"1i 17i _10i [a]i .5i Ip"];
+test_dc![output_radix_invalid;"1o 1.9o [a]o _2o Op"];
+test_dc![nested_strings;"[[inner]p]p [[inner]p]x [[42p]sa]x lax [a[b[c]d]e]p"];
+test_dc![output_radix_fraction;"16o .5p 255.75p 0 255.5-p 2o 1.1p 8o 0 9-p"];
+test_dc![output_radix_big;"60o 3661p 1.5p 0 7-p 1000o 1234567p 256o 255.50p"];
+test_dc![line_wrapping;"2 300^p 2 300^ 16o f"];